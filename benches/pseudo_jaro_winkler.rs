@@ -25,12 +25,12 @@ fn bench_compare(c: &mut Criterion) {
     let query_names = csv::ReaderBuilder::new().has_headers(false).from_path("./tests/input/prepped_df_b.csv").unwrap().deserialize().map(|rec| {
         let rec: NameRec = rec.unwrap();
         rec.first_name
-    }).filter(|name| name.len() > 0).take(10).collect::<Vec<String>>();
+    }).filter(|name| !name.is_empty()).take(10).collect::<Vec<String>>();
 
     let candidate_names = csv::ReaderBuilder::new().has_headers(false).from_path("./tests/input/prepped_df_a.csv").unwrap().deserialize().map(|rec| {
         let rec: NameRec = rec.unwrap();
         rec.first_name
-    }).filter(|name| name.len() > 0).take(100000).collect::<Vec<String>>();
+    }).filter(|name| !name.is_empty()).take(100000).collect::<Vec<String>>();
     c.bench_function("pseudo_jaro_winkler", |b| b.iter(|| {
         pseudo_jaro_winkler(black_box(&query_names), black_box(&candidate_names),PathBuf::from("./tests/output/"), 0.8);
     }));
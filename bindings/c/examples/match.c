@@ -0,0 +1,41 @@
+/*
+ * This file is part of the IPUMS's pseudo_jaro_winkler.
+ * For copyright and licensing information, see the NOTICE and LICENSE files
+ * in this project's top-level directory, and also on-line at:
+ *   https://github.com/ipums/pseudo_jaro_winkler
+ */
+
+/* Matches a few names against a small index. See the readme in bindings/c for building it. */
+
+#include <stdio.h>
+#include "pseudo_jaro_winkler.h"
+
+static int check(PjwStatus status) {
+    if (status != PJW_STATUS_OK) {
+        fprintf(stderr, "Error: %s\n", pjw_status_message(status));
+        return 0;
+    }
+    return 1;
+}
+
+int main(void) {
+    const char *candidates[] = {"john", "jon", "jane", "ellie", "elly"};
+    const char *queries[] = {"jon", "ellie"};
+    PjwIndex *index = NULL;
+    PjwMatches *matches = NULL;
+
+    if (!check(pjw_index_build(candidates, 5, &index))) {
+        return 1;
+    }
+    if (!check(pjw_index_match(index, queries, 2, 0.8f, 0, &matches))) {
+        pjw_index_free(index);
+        return 1;
+    }
+    const PjwMatch *data = pjw_matches_data(matches);
+    for (size_t i = 0; i < pjw_matches_len(matches); i++) {
+        printf("%s %s %.2f\n", queries[data[i].query], candidates[data[i].candidate], data[i].score);
+    }
+    pjw_matches_free(matches);
+    pjw_index_free(index);
+    return 0;
+}
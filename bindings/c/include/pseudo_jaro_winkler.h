@@ -0,0 +1,189 @@
+/*
+ * This file is part of the IPUMS's pseudo_jaro_winkler.
+ * For copyright and licensing information, see the NOTICE and LICENSE files
+ * in this project's top-level directory, and also on-line at:
+ *   https://github.com/ipums/pseudo_jaro_winkler
+ */
+
+#ifndef PSEUDO_JARO_WINKLER_H
+#define PSEUDO_JARO_WINKLER_H
+
+/* Generated from src/lib.rs by cbindgen, see tests/header.rs. Don't modify this file by hand. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+/**
+ * The result of calling a function.
+ */
+typedef enum PjwStatus {
+  /**
+   * The call succeeded.
+   */
+  PJW_STATUS_OK = 0,
+  /**
+   * A pointer argument was null.
+   */
+  PJW_STATUS_NULL_POINTER = 1,
+  /**
+   * A name wasn't valid UTF-8, or wasn't a valid name. See `is_valid_name`.
+   */
+  PJW_STATUS_INVALID_NAME = 2,
+  /**
+   * Reading or writing an index file failed.
+   */
+  PJW_STATUS_IO_ERROR = 3,
+  /**
+   * A position was past the end of the matches.
+   */
+  PJW_STATUS_OUT_OF_RANGE = 4,
+  /**
+   * An unexpected error inside the library.
+   */
+  PJW_STATUS_INTERNAL_ERROR = 5,
+} PjwStatus;
+
+/**
+ * An index of candidate names to match names against.
+ */
+typedef struct PjwIndex PjwIndex;
+
+/**
+ * The matches found by `pjw_index_match`.
+ */
+typedef struct PjwMatches PjwMatches;
+
+/**
+ * A single match between a query and a candidate.
+ */
+typedef struct PjwMatch {
+  /**
+   * The position of the name in the queries.
+   */
+  size_t query;
+  /**
+   * The position of the candidate in the names the index was built from.
+   */
+  size_t candidate;
+  /**
+   * The pseudo jaro winkler score of the match.
+   */
+  float score;
+} PjwMatch;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ * Builds an index of candidate names. Matches refer to each candidate by its position in `names`.
+ * On success the index is stored in `out`, and must be freed with `pjw_index_free`.
+ *
+ * # Safety
+ *
+ * `names` must point to `len` NUL terminated strings, and `out` must be a valid pointer.
+ */
+enum PjwStatus pjw_index_build(const char *const *names, size_t len, struct PjwIndex **out);
+
+/**
+ * Opens an index file written by `pjw_index_save` without reading it into memory. On success the
+ * index is stored in `out`, and must be freed with `pjw_index_free`.
+ *
+ * # Safety
+ *
+ * `path` must be a NUL terminated string, and `out` must be a valid pointer.
+ */
+enum PjwStatus pjw_index_open(const char *path, struct PjwIndex **out);
+
+/**
+ * Writes an index to a file which can be opened with `pjw_index_open`.
+ *
+ * # Safety
+ *
+ * `index` must have been created by this library and not freed, and `path` must be a NUL
+ * terminated string.
+ */
+enum PjwStatus pjw_index_save(const struct PjwIndex *index, const char *path);
+
+/**
+ * Frees an index. Does nothing if `index` is null.
+ *
+ * # Safety
+ *
+ * `index` must be null or have been created by this library and not already freed.
+ */
+void pjw_index_free(struct PjwIndex *index);
+
+/**
+ * Matches `len` names against the candidates in an index, in parallel. On success the matches are
+ * stored in `out`, and must be freed with `pjw_matches_free`. The matches are ordered by query,
+ * and the matches of each query are from best to worst when keeping the `top_k` best.
+ *
+ * # Arguments
+ *
+ * * `min_score`: The minimum score of a match.
+ * * `top_k`: Only keep the best `top_k` matches of each query, or all of them if it is 0.
+ *
+ * # Safety
+ *
+ * `index` must have been created by this library and not freed, `queries` must point to `len`
+ * NUL terminated strings, and `out` must be a valid pointer.
+ */
+enum PjwStatus pjw_index_match(const struct PjwIndex *index,
+                               const char *const *queries,
+                               size_t len,
+                               float min_score,
+                               size_t top_k,
+                               struct PjwMatches **out);
+
+/**
+ * The number of matches. Returns 0 if `matches` is null.
+ *
+ * # Safety
+ *
+ * `matches` must be null or have been created by this library and not freed.
+ */
+size_t pjw_matches_len(const struct PjwMatches *matches);
+
+/**
+ * A pointer to the array of `pjw_matches_len` matches, which lives until the matches are freed.
+ * Returns null if `matches` is null.
+ *
+ * # Safety
+ *
+ * `matches` must be null or have been created by this library and not freed.
+ */
+const struct PjwMatch *pjw_matches_data(const struct PjwMatches *matches);
+
+/**
+ * Copies the match at position `i` into `out`.
+ *
+ * # Safety
+ *
+ * `matches` must have been created by this library and not freed, and `out` must be a valid
+ * pointer.
+ */
+enum PjwStatus pjw_matches_get(const struct PjwMatches *matches, size_t i, struct PjwMatch *out);
+
+/**
+ * Frees the matches. Does nothing if `matches` is null.
+ *
+ * # Safety
+ *
+ * `matches` must be null or have been created by this library and not already freed.
+ */
+void pjw_matches_free(struct PjwMatches *matches);
+
+/**
+ * A description of a status, which must not be freed.
+ */
+const char *pjw_status_message(enum PjwStatus status);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* PSEUDO_JARO_WINKLER_H */
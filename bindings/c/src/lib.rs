@@ -0,0 +1,256 @@
+// This file is part of the IPUMS's pseudo_jaro_winkler.
+// For copyright and licensing information, see the NOTICE and LICENSE files
+// in this project's top-level directory, and also on-line at:
+//   https://github.com/ipums/pseudo_jaro_winkler
+
+//! A C API for pseudo_jaro_winkler, for calling it from languages that can call C libraries. The
+//! header, `include/pseudo_jaro_winkler.h`, is generated from this file, see `tests/header.rs`.
+//!
+//! Every function that can fail returns a `PjwStatus` rather than panicking, and every object
+//! created by the library must be freed with the library's matching `_free` function.
+
+use std::ffi::{c_char, CStr, CString};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::ptr;
+use std::slice;
+use std::sync::OnceLock;
+use rayon::prelude::*;
+use pseudo_jaro_winkler::{is_valid_name, CandidateIndex, MatchOptions, VALID_NAME_DESCRIPTION};
+
+/// The result of calling a function.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PjwStatus {
+    /// The call succeeded.
+    Ok = 0,
+    /// A pointer argument was null.
+    NullPointer = 1,
+    /// A name wasn't valid UTF-8, or wasn't a valid name. See `is_valid_name`.
+    InvalidName = 2,
+    /// Reading or writing an index file failed.
+    IoError = 3,
+    /// A position was past the end of the matches.
+    OutOfRange = 4,
+    /// An unexpected error inside the library.
+    InternalError = 5,
+}
+
+/// An index of candidate names to match names against.
+pub struct PjwIndex {
+    candidate_index: CandidateIndex,
+}
+
+/// A single match between a query and a candidate.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PjwMatch {
+    /// The position of the name in the queries.
+    pub query: usize,
+    /// The position of the candidate in the names the index was built from.
+    pub candidate: usize,
+    /// The pseudo jaro winkler score of the match.
+    pub score: f32,
+}
+
+/// The matches found by `pjw_index_match`.
+pub struct PjwMatches {
+    matches: Vec<PjwMatch>,
+}
+
+/// Runs the body of a function, turning any panic into `PjwStatus::InternalError` so that it
+/// doesn't unwind into the caller.
+fn catch_panics<F: FnOnce() -> Result<(), PjwStatus>>(body: F) -> PjwStatus {
+    match catch_unwind(AssertUnwindSafe(body)) {
+        Ok(Ok(())) => PjwStatus::Ok,
+        Ok(Err(status)) => status,
+        Err(_) => PjwStatus::InternalError,
+    }
+}
+
+/// Reads `len` NUL terminated names, checking that they can be scored.
+unsafe fn read_names<'a>(names: *const *const c_char, len: usize) -> Result<Vec<&'a str>, PjwStatus> {
+    if len == 0 {
+        return Ok(Vec::new());
+    }
+    if names.is_null() {
+        return Err(PjwStatus::NullPointer);
+    }
+    slice::from_raw_parts(names, len).iter().map(|&name| read_str(name)?.filter(|name| is_valid_name(name)).ok_or(PjwStatus::InvalidName)).collect()
+}
+
+/// Reads a NUL terminated string, returning `None` if it isn't valid UTF-8.
+unsafe fn read_str<'a>(string: *const c_char) -> Result<Option<&'a str>, PjwStatus> {
+    if string.is_null() {
+        return Err(PjwStatus::NullPointer);
+    }
+    Ok(CStr::from_ptr(string).to_str().ok())
+}
+
+/// Stores a new object in `out`, which must not be null.
+unsafe fn write_out<T>(out: *mut *mut T, value: T) {
+    *out = Box::into_raw(Box::new(value));
+}
+
+/// Builds an index of candidate names. Matches refer to each candidate by its position in `names`.
+/// On success the index is stored in `out`, and must be freed with `pjw_index_free`.
+///
+/// # Safety
+///
+/// `names` must point to `len` NUL terminated strings, and `out` must be a valid pointer.
+#[no_mangle]
+pub unsafe extern "C" fn pjw_index_build(names: *const *const c_char, len: usize, out: *mut *mut PjwIndex) -> PjwStatus {
+    catch_panics(|| {
+        if out.is_null() {
+            return Err(PjwStatus::NullPointer);
+        }
+        *out = ptr::null_mut();
+        let names = read_names(names, len)?.into_iter().map(str::to_string).collect::<Vec<String>>();
+        write_out(out, PjwIndex { candidate_index: CandidateIndex::new(&names) });
+        Ok(())
+    })
+}
+
+/// Opens an index file written by `pjw_index_save` without reading it into memory. On success the
+/// index is stored in `out`, and must be freed with `pjw_index_free`.
+///
+/// # Safety
+///
+/// `path` must be a NUL terminated string, and `out` must be a valid pointer.
+#[no_mangle]
+pub unsafe extern "C" fn pjw_index_open(path: *const c_char, out: *mut *mut PjwIndex) -> PjwStatus {
+    catch_panics(|| {
+        if out.is_null() {
+            return Err(PjwStatus::NullPointer);
+        }
+        *out = ptr::null_mut();
+        let path = read_str(path)?.ok_or(PjwStatus::IoError)?;
+        let candidate_index = CandidateIndex::open(path).map_err(|_| PjwStatus::IoError)?;
+        write_out(out, PjwIndex { candidate_index });
+        Ok(())
+    })
+}
+
+/// Writes an index to a file which can be opened with `pjw_index_open`.
+///
+/// # Safety
+///
+/// `index` must have been created by this library and not freed, and `path` must be a NUL
+/// terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn pjw_index_save(index: *const PjwIndex, path: *const c_char) -> PjwStatus {
+    catch_panics(|| {
+        let index = index.as_ref().ok_or(PjwStatus::NullPointer)?;
+        let path = read_str(path)?.ok_or(PjwStatus::IoError)?;
+        index.candidate_index.save(path).map_err(|_| PjwStatus::IoError)
+    })
+}
+
+/// Frees an index. Does nothing if `index` is null.
+///
+/// # Safety
+///
+/// `index` must be null or have been created by this library and not already freed.
+#[no_mangle]
+pub unsafe extern "C" fn pjw_index_free(index: *mut PjwIndex) {
+    if !index.is_null() {
+        drop(Box::from_raw(index));
+    }
+}
+
+/// Matches `len` names against the candidates in an index, in parallel. On success the matches are
+/// stored in `out`, and must be freed with `pjw_matches_free`. The matches are ordered by query,
+/// and the matches of each query are from best to worst when keeping the `top_k` best.
+///
+/// # Arguments
+///
+/// * `min_score`: The minimum score of a match.
+/// * `top_k`: Only keep the best `top_k` matches of each query, or all of them if it is 0.
+///
+/// # Safety
+///
+/// `index` must have been created by this library and not freed, `queries` must point to `len`
+/// NUL terminated strings, and `out` must be a valid pointer.
+#[no_mangle]
+pub unsafe extern "C" fn pjw_index_match(index: *const PjwIndex, queries: *const *const c_char, len: usize, min_score: f32, top_k: usize, out: *mut *mut PjwMatches) -> PjwStatus {
+    catch_panics(|| {
+        if out.is_null() {
+            return Err(PjwStatus::NullPointer);
+        }
+        *out = ptr::null_mut();
+        let index = index.as_ref().ok_or(PjwStatus::NullPointer)?;
+        let queries = read_names(queries, len)?;
+        let options = MatchOptions { top_k: if top_k == 0 { None } else { Some(top_k) }, ..MatchOptions::new(min_score) };
+        let matches = queries.par_iter().enumerate().map(|(query, query_name)| {
+            let query_matches = index.candidate_index.matches(query_name, &options).map_err(|_| PjwStatus::InvalidName)?;
+            Ok(query_matches.into_iter().map(move |(candidate, score)| PjwMatch { query, candidate, score }))
+        }).collect::<Result<Vec<_>, PjwStatus>>()?.into_iter().flatten().collect();
+        write_out(out, PjwMatches { matches });
+        Ok(())
+    })
+}
+
+/// The number of matches. Returns 0 if `matches` is null.
+///
+/// # Safety
+///
+/// `matches` must be null or have been created by this library and not freed.
+#[no_mangle]
+pub unsafe extern "C" fn pjw_matches_len(matches: *const PjwMatches) -> usize {
+    matches.as_ref().map_or(0, |matches| matches.matches.len())
+}
+
+/// A pointer to the array of `pjw_matches_len` matches, which lives until the matches are freed.
+/// Returns null if `matches` is null.
+///
+/// # Safety
+///
+/// `matches` must be null or have been created by this library and not freed.
+#[no_mangle]
+pub unsafe extern "C" fn pjw_matches_data(matches: *const PjwMatches) -> *const PjwMatch {
+    matches.as_ref().map_or(ptr::null(), |matches| matches.matches.as_ptr())
+}
+
+/// Copies the match at position `i` into `out`.
+///
+/// # Safety
+///
+/// `matches` must have been created by this library and not freed, and `out` must be a valid
+/// pointer.
+#[no_mangle]
+pub unsafe extern "C" fn pjw_matches_get(matches: *const PjwMatches, i: usize, out: *mut PjwMatch) -> PjwStatus {
+    catch_panics(|| {
+        let matches = matches.as_ref().ok_or(PjwStatus::NullPointer)?;
+        let out = out.as_mut().ok_or(PjwStatus::NullPointer)?;
+        *out = *matches.matches.get(i).ok_or(PjwStatus::OutOfRange)?;
+        Ok(())
+    })
+}
+
+/// Frees the matches. Does nothing if `matches` is null.
+///
+/// # Safety
+///
+/// `matches` must be null or have been created by this library and not already freed.
+#[no_mangle]
+pub unsafe extern "C" fn pjw_matches_free(matches: *mut PjwMatches) {
+    if !matches.is_null() {
+        drop(Box::from_raw(matches));
+    }
+}
+
+/// A description of a status, which must not be freed.
+#[no_mangle]
+pub extern "C" fn pjw_status_message(status: PjwStatus) -> *const c_char {
+    let message: &'static CStr = match status {
+        PjwStatus::Ok => c"ok",
+        PjwStatus::NullPointer => c"a pointer argument was null",
+        PjwStatus::InvalidName => {
+            static MESSAGE: OnceLock<CString> = OnceLock::new();
+            MESSAGE.get_or_init(|| CString::new(format!("a name wasn't valid, {}", VALID_NAME_DESCRIPTION)).unwrap())
+        },
+        PjwStatus::IoError => c"reading or writing the index file failed",
+        PjwStatus::OutOfRange => c"the position was past the end of the matches",
+        PjwStatus::InternalError => c"an unexpected error happened inside the library",
+    };
+    message.as_ptr()
+}
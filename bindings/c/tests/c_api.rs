@@ -0,0 +1,96 @@
+// This file is part of the IPUMS's pseudo_jaro_winkler.
+// For copyright and licensing information, see the NOTICE and LICENSE files
+// in this project's top-level directory, and also on-line at:
+//   https://github.com/ipums/pseudo_jaro_winkler
+
+use std::ffi::{CStr, CString};
+use std::os::raw::c_char;
+use std::ptr;
+use pseudo_jaro_winkler_c::*;
+
+fn c_strings(names: &[&str]) -> Vec<CString> {
+    names.iter().map(|&name| CString::new(name).unwrap()).collect()
+}
+
+fn pointers(strings: &[CString]) -> Vec<*const c_char> {
+    strings.iter().map(|string| string.as_ptr()).collect()
+}
+
+#[test]
+fn test_build_match_free() {
+    let candidates = c_strings(&["john", "jon", "jane", "ellie", "elly"]);
+    let queries = c_strings(&["jon", "ellie", "zzz"]);
+    unsafe {
+        let mut index = ptr::null_mut();
+        assert_eq!(pjw_index_build(pointers(&candidates).as_ptr(), candidates.len(), &mut index), PjwStatus::Ok);
+        let mut matches = ptr::null_mut();
+        assert_eq!(pjw_index_match(index, pointers(&queries).as_ptr(), queries.len(), 0.8, 1, &mut matches), PjwStatus::Ok);
+        let found = std::slice::from_raw_parts(pjw_matches_data(matches), pjw_matches_len(matches));
+        assert_eq!(found.iter().map(|m| (m.query, m.candidate)).collect::<Vec<_>>(), vec![(0, 1), (1, 3)]);
+        assert!((found[1].score - 1.0).abs() < 0.01);
+
+        let mut first = PjwMatch { query: 9, candidate: 9, score: 0.0 };
+        assert_eq!(pjw_matches_get(matches, 0, &mut first), PjwStatus::Ok);
+        assert_eq!(first, found[0]);
+        assert_eq!(pjw_matches_get(matches, 2, &mut first), PjwStatus::OutOfRange);
+        pjw_matches_free(matches);
+
+        let mut all_matches = ptr::null_mut();
+        assert_eq!(pjw_index_match(index, pointers(&queries).as_ptr(), queries.len(), 0.8, 0, &mut all_matches), PjwStatus::Ok);
+        assert_eq!(pjw_matches_len(all_matches), 4);
+        pjw_matches_free(all_matches);
+        pjw_index_free(index);
+    }
+}
+
+#[test]
+fn test_save_open() {
+    let candidates = c_strings(&["john", "jon", "jane"]);
+    let queries = c_strings(&["jon"]);
+    let path = CString::new(std::env::temp_dir().join("pseudo_jaro_winkler_c_test.idx").to_str().unwrap()).unwrap();
+    unsafe {
+        let mut index = ptr::null_mut();
+        assert_eq!(pjw_index_build(pointers(&candidates).as_ptr(), candidates.len(), &mut index), PjwStatus::Ok);
+        assert_eq!(pjw_index_save(index, path.as_ptr()), PjwStatus::Ok);
+        pjw_index_free(index);
+
+        let mut opened = ptr::null_mut();
+        assert_eq!(pjw_index_open(path.as_ptr(), &mut opened), PjwStatus::Ok);
+        let mut matches = ptr::null_mut();
+        assert_eq!(pjw_index_match(opened, pointers(&queries).as_ptr(), 1, 0.9, 0, &mut matches), PjwStatus::Ok);
+        assert_eq!(pjw_matches_len(matches), 2);
+        pjw_matches_free(matches);
+        pjw_index_free(opened);
+
+        let missing = CString::new("/nonexistent/pseudo_jaro_winkler.idx").unwrap();
+        let mut index = ptr::null_mut();
+        assert_eq!(pjw_index_open(missing.as_ptr(), &mut index), PjwStatus::IoError);
+        assert!(index.is_null());
+    }
+    std::fs::remove_file(path.to_str().unwrap()).unwrap();
+}
+
+#[test]
+fn test_errors() {
+    let invalid = c_strings(&["john", "John"]);
+    let mut with_null = pointers(&c_strings(&["john"]));
+    with_null.push(ptr::null());
+    unsafe {
+        let mut index = ptr::null_mut();
+        assert_eq!(pjw_index_build(pointers(&invalid).as_ptr(), invalid.len(), &mut index), PjwStatus::InvalidName);
+        assert!(index.is_null());
+        assert_eq!(pjw_index_build(ptr::null(), 1, &mut index), PjwStatus::NullPointer);
+        assert_eq!(pjw_index_build(pointers(&invalid).as_ptr(), 1, ptr::null_mut()), PjwStatus::NullPointer);
+        let mut matches = ptr::null_mut();
+        assert_eq!(pjw_index_match(ptr::null(), ptr::null(), 0, 0.8, 0, &mut matches), PjwStatus::NullPointer);
+        assert!(matches.is_null());
+        assert_eq!(pjw_matches_len(ptr::null()), 0);
+        assert!(pjw_matches_data(ptr::null()).is_null());
+        pjw_matches_free(ptr::null_mut());
+        pjw_index_free(ptr::null_mut());
+
+        let non_utf8 = [0xffu8 as c_char, 0];
+        assert_eq!(pjw_index_build([non_utf8.as_ptr()].as_ptr(), 1, &mut index), PjwStatus::InvalidName);
+        assert_eq!(CStr::from_ptr(pjw_status_message(PjwStatus::InvalidName)).to_str().unwrap(), format!("a name wasn't valid, {}", pseudo_jaro_winkler::VALID_NAME_DESCRIPTION));
+    }
+}
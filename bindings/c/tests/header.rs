@@ -0,0 +1,26 @@
+// This file is part of the IPUMS's pseudo_jaro_winkler.
+// For copyright and licensing information, see the NOTICE and LICENSE files
+// in this project's top-level directory, and also on-line at:
+//   https://github.com/ipums/pseudo_jaro_winkler
+
+//! Checks that the checked in C header, `include/pseudo_jaro_winkler.h`, matches the functions in
+//! `src/lib.rs`. Set `PJW_WRITE_HEADER=1` to regenerate it instead.
+
+use std::fs::read_to_string;
+
+#[test]
+fn test_header_is_up_to_date() {
+    let crate_dir = env!("CARGO_MANIFEST_DIR");
+    let header_path = format!("{}/include/pseudo_jaro_winkler.h", crate_dir);
+    let bindings = cbindgen::generate(crate_dir).expect("Unable to generate the C header");
+    if std::env::var_os("PJW_WRITE_HEADER").is_some() {
+        bindings.write_to_file(&header_path);
+        return;
+    }
+    let mut generated = Vec::new();
+    bindings.write(&mut generated);
+    assert!(
+        String::from_utf8(generated).unwrap() == read_to_string(&header_path).unwrap(),
+        "{} is out of date, regenerate it with `PJW_WRITE_HEADER=1 cargo test -p pseudo_jaro_winkler_c --test header`", header_path,
+    );
+}
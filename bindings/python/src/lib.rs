@@ -0,0 +1,99 @@
+// This file is part of the IPUMS's pseudo_jaro_winkler.
+// For copyright and licensing information, see the NOTICE and LICENSE files
+// in this project's top-level directory, and also on-line at:
+//   https://github.com/ipums/pseudo_jaro_winkler
+
+//! Python bindings for pseudo_jaro_winkler, built with maturin. See the readme in this directory.
+
+use std::path::PathBuf;
+use numpy::IntoPyArray;
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+use rayon::prelude::*;
+use pseudo_jaro_winkler::{is_valid_name, EmptyNameError, MatchOptions, VALID_NAME_DESCRIPTION};
+
+/// An index of candidate names to match names against. Build it with `CandidateIndex.build` or
+/// load a saved one with `CandidateIndex.load`.
+#[pyclass(name = "CandidateIndex", module = "pseudo_jaro_winkler", frozen)]
+struct CandidateIndex {
+    candidate_index: pseudo_jaro_winkler::CandidateIndex,
+}
+
+#[pymethods]
+impl CandidateIndex {
+    /// Builds an index of a list of candidate names. Matches refer to each candidate by its
+    /// position in the list.
+    #[staticmethod]
+    fn build(py: Python<'_>, names: Vec<String>) -> PyResult<CandidateIndex> {
+        check_names(&names)?;
+        Ok(CandidateIndex { candidate_index: py.detach(|| pseudo_jaro_winkler::CandidateIndex::new(&names)) })
+    }
+
+    /// Opens an index saved with `save` without reading it into memory, so that processes loading
+    /// the same file share a single copy of it.
+    #[staticmethod]
+    fn load(py: Python<'_>, path: PathBuf) -> PyResult<CandidateIndex> {
+        let candidate_index = py.detach(|| pseudo_jaro_winkler::CandidateIndex::open(path))?;
+        Ok(CandidateIndex { candidate_index })
+    }
+
+    /// Writes the index to a file which can be opened with `load`.
+    fn save(&self, py: Python<'_>, path: PathBuf) -> PyResult<()> {
+        py.detach(|| self.candidate_index.save(path))?;
+        Ok(())
+    }
+
+    /// Matches a list of names against the candidates in parallel. Returns a tuple of numpy arrays
+    /// `(i, j, score)` with the position of the name in `queries`, the position of the candidate
+    /// and the score of every match, or a pandas DataFrame with those columns if `as_frame` is
+    /// true. The matches of each name are from best to worst when keeping the `top_k` best.
+    #[pyo3(name = "match", signature = (queries, min_score = 0.8, top_k = None, as_frame = false))]
+    fn match_names<'py>(&self, py: Python<'py>, queries: Vec<String>, min_score: f32, top_k: Option<usize>, as_frame: bool) -> PyResult<Bound<'py, PyAny>> {
+        check_names(&queries)?;
+        // Raises an ImportError rather than panicking when creating the arrays if numpy is missing
+        py.import("numpy")?;
+        if top_k == Some(0) {
+            return Err(PyValueError::new_err("top_k must be greater than 0"));
+        }
+        let options = MatchOptions { top_k, ..MatchOptions::new(min_score) };
+        let (i, j, score) = py.detach(|| {
+            let matches = queries.par_iter().map(|query_name| self.candidate_index.matches(query_name, &options)).collect::<Result<Vec<_>, _>>()?;
+            let match_count = matches.iter().map(Vec::len).sum();
+            let (mut i, mut j, mut score) = (Vec::with_capacity(match_count), Vec::with_capacity(match_count), Vec::with_capacity(match_count));
+            matches.into_iter().enumerate().for_each(|(query_index, query_matches)| {
+                query_matches.into_iter().for_each(|(b_id, jw)| {
+                    i.push(query_index as i64);
+                    j.push(b_id as i64);
+                    score.push(jw);
+                });
+            });
+            Ok((i, j, score))
+        }).map_err(|e: EmptyNameError| PyValueError::new_err(e.to_string()))?;
+        let (i, j, score) = (i.into_pyarray(py), j.into_pyarray(py), score.into_pyarray(py));
+        if as_frame {
+            let columns = PyDict::new(py);
+            columns.set_item("i", i)?;
+            columns.set_item("j", j)?;
+            columns.set_item("score", score)?;
+            py.import("pandas")?.call_method1("DataFrame", (columns,))
+        } else {
+            Ok((i, j, score).into_pyobject(py)?.into_any())
+        }
+    }
+}
+
+/// Raises a `ValueError` for the first name which can't be scored.
+fn check_names(names: &[String]) -> PyResult<()> {
+    match names.iter().position(|name| !is_valid_name(name)) {
+        Some(position) => Err(PyValueError::new_err(format!("invalid name {:?} at position {}: {}", names[position], position, VALID_NAME_DESCRIPTION))),
+        None => Ok(()),
+    }
+}
+
+#[pymodule]
+#[pyo3(name = "pseudo_jaro_winkler")]
+fn pseudo_jaro_winkler_python(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    module.add_class::<CandidateIndex>()?;
+    Ok(())
+}
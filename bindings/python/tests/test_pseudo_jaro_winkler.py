@@ -0,0 +1,55 @@
+# This file is part of the IPUMS's pseudo_jaro_winkler.
+# For copyright and licensing information, see the NOTICE and LICENSE files
+# in this project's top-level directory, and also on-line at:
+#   https://github.com/ipums/pseudo_jaro_winkler
+
+import numpy as np
+import pytest
+
+import pseudo_jaro_winkler as pjw
+
+CANDIDATES = ["ellie", "nellie", "ellen", "john", "jon", "ellie"]
+
+
+def test_match():
+    index = pjw.CandidateIndex.build(CANDIDATES)
+    i, j, score = index.match(["ellie", "jon"])
+    assert i.dtype == np.int64 and j.dtype == np.int64 and score.dtype == np.float32
+    matches = sorted(zip(i.tolist(), j.tolist()))
+    assert matches == [(0, 0), (0, 1), (0, 2), (0, 5), (1, 3), (1, 4)]
+    assert (score >= 0.8).all()
+
+
+def test_top_k():
+    index = pjw.CandidateIndex.build(CANDIDATES)
+    i, j, score = index.match(["ellie"], min_score=0.5, top_k=2)
+    assert i.tolist() == [0, 0]
+    assert j.tolist() == [0, 5]
+    with pytest.raises(ValueError):
+        index.match(["ellie"], top_k=0)
+
+
+def test_as_frame():
+    pytest.importorskip("pandas")
+    index = pjw.CandidateIndex.build(CANDIDATES)
+    frame = index.match(["ellie", "jon"], as_frame=True)
+    assert list(frame.columns) == ["i", "j", "score"]
+    assert len(frame) == 6
+
+
+def test_save_load(tmp_path):
+    index = pjw.CandidateIndex.build(CANDIDATES)
+    index.save(tmp_path / "candidates.idx")
+    loaded = pjw.CandidateIndex.load(tmp_path / "candidates.idx")
+    for expected, actual in zip(index.match(["ellie", "jon"]), loaded.match(["ellie", "jon"])):
+        np.testing.assert_array_equal(expected, actual)
+    with pytest.raises(OSError):
+        pjw.CandidateIndex.load(tmp_path / "missing.idx")
+
+
+def test_invalid_names():
+    with pytest.raises(ValueError):
+        pjw.CandidateIndex.build(["Ellie"])
+    index = pjw.CandidateIndex.build(CANDIDATES)
+    with pytest.raises(ValueError):
+        index.match([""])
@@ -0,0 +1,53 @@
+// This file is part of the IPUMS's pseudo_jaro_winkler.
+// For copyright and licensing information, see the NOTICE and LICENSE files
+// in this project's top-level directory, and also on-line at:
+//   https://github.com/ipums/pseudo_jaro_winkler
+
+//! R bindings for pseudo_jaro_winkler, built with extendr when installing the R package. The R
+//! function `pjw_match` in `R/pjw_match.R` checks its arguments and calls `match_names`.
+
+use extendr_api::prelude::*;
+use rayon::prelude::*;
+use pseudo_jaro_winkler::{is_valid_name, CandidateIndex, MatchOptions, VALID_NAME_DESCRIPTION};
+
+/// Matches every name in `names_a` against the names in `names_b` in parallel, returning a list of
+/// the 1-based positions `i` and `j` of the names in each match and its score `jw`. A `top_k` of 0
+/// keeps every match at or above `min_score`.
+/// @noRd
+#[extendr]
+fn match_names(names_a: Strings, names_b: Strings, min_score: f64, top_k: i32) -> Result<List> {
+    let names_a = check_names(&names_a, "names_a")?;
+    let names_b = check_names(&names_b, "names_b")?;
+    let candidate_index = CandidateIndex::new(&names_b);
+    let options = MatchOptions { top_k: if top_k > 0 { Some(top_k as usize) } else { None }, ..MatchOptions::new(min_score as f32) };
+    let matches = names_a.par_iter().map(|name| candidate_index.matches(name, &options)).collect::<std::result::Result<Vec<_>, _>>().map_err(|e| Error::Other(e.to_string()))?;
+    let match_count = matches.iter().map(Vec::len).sum();
+    let (mut i, mut j, mut jw) = (Vec::with_capacity(match_count), Vec::with_capacity(match_count), Vec::with_capacity(match_count));
+    matches.into_iter().enumerate().for_each(|(a_index, a_matches)| {
+        a_matches.into_iter().for_each(|(b_index, score)| {
+            i.push(a_index as i32 + 1);
+            j.push(b_index as i32 + 1);
+            jw.push(score as f64);
+        });
+    });
+    Ok(list!(i = i, j = j, jw = jw))
+}
+
+/// Copies the names out of R, raising an error for the first one which can't be scored.
+fn check_names(names: &Strings, label: &str) -> Result<Vec<String>> {
+    if names.len() > i32::MAX as usize {
+        return Err(Error::Other(format!("{} has more names than R can index", label)));
+    }
+    names.iter().enumerate().map(|(position, name)| {
+        if name.is_na() || !is_valid_name(name.as_str()) {
+            Err(Error::Other(format!("invalid name {:?} at position {} of {}: {}", name.as_str(), position + 1, label, VALID_NAME_DESCRIPTION)))
+        } else {
+            Ok(name.to_string())
+        }
+    }).collect()
+}
+
+extendr_module! {
+    mod pseudojarowinkler;
+    fn match_names;
+}
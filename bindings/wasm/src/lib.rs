@@ -0,0 +1,69 @@
+// This file is part of the IPUMS's pseudo_jaro_winkler.
+// For copyright and licensing information, see the NOTICE and LICENSE files
+// in this project's top-level directory, and also on-line at:
+//   https://github.com/ipums/pseudo_jaro_winkler
+
+//! WebAssembly bindings for pseudo_jaro_winkler, built with wasm-pack. See the readme in this
+//! directory.
+//!
+//! The library is built without its default features, so that it doesn't need threads or files.
+
+use wasm_bindgen::prelude::*;
+use pseudo_jaro_winkler::{is_valid_name, keep_top_k, MatchOptions, VALID_NAME_DESCRIPTION};
+
+/// An index of candidate names to look up names in.
+#[wasm_bindgen]
+pub struct CandidateIndex {
+    candidate_index: pseudo_jaro_winkler::CandidateIndex,
+    names: Vec<String>,
+}
+
+/// A candidate similar to the name looked up.
+#[wasm_bindgen(getter_with_clone)]
+pub struct Suggestion {
+    /// The position of the candidate in the names the index was built from.
+    pub index: usize,
+    /// The name of the candidate.
+    pub name: String,
+    /// The pseudo jaro winkler score of the candidate.
+    pub score: f32,
+}
+
+#[wasm_bindgen]
+impl CandidateIndex {
+    /// Builds an index of a list of candidate names. Throws an error if a name isn't valid, see
+    /// `is_valid_name`.
+    #[wasm_bindgen(constructor)]
+    pub fn new(names: Vec<String>) -> Result<CandidateIndex, JsError> {
+        check_names(&names)?;
+        Ok(CandidateIndex { candidate_index: pseudo_jaro_winkler::CandidateIndex::new(&names), names })
+    }
+
+    /// The number of candidate names.
+    #[wasm_bindgen(getter)]
+    pub fn length(&self) -> usize {
+        self.names.len()
+    }
+
+    /// Looks up the candidates similar to a name, from best to worst.
+    ///
+    /// # Arguments
+    ///
+    /// * `query`: The name to look up.
+    /// * `min_score`: The minimum score of a suggestion.
+    /// * `top_k`: If given, only return the best `top_k` suggestions.
+    pub fn suggest(&self, query: &str, min_score: f32, top_k: Option<usize>) -> Result<Vec<Suggestion>, JsError> {
+        check_names(&[query])?;
+        let mut matches = self.candidate_index.matches(query, &MatchOptions::new(min_score)).map_err(|e| JsError::new(&e.to_string()))?;
+        keep_top_k(&mut matches, top_k.unwrap_or(usize::MAX));
+        Ok(matches.into_iter().map(|(index, score)| Suggestion { index, name: self.names[index].clone(), score }).collect())
+    }
+}
+
+/// Throws an error for the first name which can't be scored.
+fn check_names<S: AsRef<str>>(names: &[S]) -> Result<(), JsError> {
+    match names.iter().map(AsRef::as_ref).find(|name| !is_valid_name(name)) {
+        Some(name) => Err(JsError::new(&format!("invalid name {:?}: {}", name, VALID_NAME_DESCRIPTION))),
+        None => Ok(()),
+    }
+}
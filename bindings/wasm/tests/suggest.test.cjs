@@ -0,0 +1,34 @@
+// This file is part of the IPUMS's pseudo_jaro_winkler.
+// For copyright and licensing information, see the NOTICE and LICENSE files
+// in this project's top-level directory, and also on-line at:
+//   https://github.com/ipums/pseudo_jaro_winkler
+
+// Run with `node --test tests/` after building the package with `wasm-pack build --target nodejs`.
+
+const test = require("node:test");
+const assert = require("node:assert");
+const { CandidateIndex } = require("../pkg/pseudo_jaro_winkler_wasm.js");
+
+const candidates = ["john", "jon", "jane", "ellie", "elly", "nellie"];
+
+test("suggest returns the candidates above the threshold from best to worst", () => {
+  const index = new CandidateIndex(candidates);
+  assert.strictEqual(index.length, 6);
+  const suggestions = index.suggest("jon", 0.8);
+  assert.deepStrictEqual(suggestions.map((s) => [s.index, s.name]), [[1, "jon"], [0, "john"]]);
+  assert.ok(Math.abs(suggestions[0].score - 1.0) < 0.01);
+  assert.ok(suggestions[0].score >= suggestions[1].score);
+});
+
+test("suggest keeps the best top_k suggestions", () => {
+  const index = new CandidateIndex(candidates);
+  assert.deepStrictEqual(index.suggest("ellie", 0.8, 1).map((s) => s.name), ["ellie"]);
+  assert.strictEqual(index.suggest("ellie", 0.8).length, 3);
+  assert.strictEqual(index.suggest("zzz", 0.8).length, 0);
+});
+
+test("invalid names throw errors", () => {
+  assert.throws(() => new CandidateIndex(["john", "John"]), /names must be 1 to 16 lowercase letters, spaces or '`'/);
+  const index = new CandidateIndex(candidates);
+  assert.throws(() => index.suggest("", 0.8), /names must be 1 to 16 lowercase letters, spaces or '`'/);
+});
@@ -0,0 +1,48 @@
+// This file is part of the IPUMS's pseudo_jaro_winkler.
+// For copyright and licensing information, see the NOTICE and LICENSE files
+// in this project's top-level directory, and also on-line at:
+//   https://github.com/ipums/pseudo_jaro_winkler
+
+//! Builds indexes of any names, including invalid ones, inserts and removes candidates, and saves
+//! and reopens the index, making sure that nothing panics, every score is within [0, 1] and the
+//! reopened index matches the same.
+
+#![no_main]
+use arbitrary::Arbitrary;
+use libfuzzer_sys::fuzz_target;
+use std::collections::HashSet;
+use pseudo_jaro_winkler::{is_valid_name, CandidateIndex, MatchOptions};
+
+#[derive(Arbitrary, Debug)]
+struct Input {
+    names: Vec<String>,
+    first_id: u16,
+    inserted: Vec<(u16, String)>,
+    removed: Vec<u16>,
+}
+
+fuzz_target!(|input: Input| {
+    let mut candidate_index = CandidateIndex::new_shard(&input.names, input.first_id as usize);
+    let mut inserted_b_ids = HashSet::new();
+    for (b_id, name) in input.inserted {
+        let b_id = input.first_id as usize + input.names.len() + b_id as usize;
+        if inserted_b_ids.insert(b_id) {
+            candidate_index.insert(b_id, &name);
+        }
+    }
+    input.removed.iter().for_each(|&b_id| { candidate_index.remove(b_id as usize); });
+    let path = std::env::temp_dir().join(format!("pseudo_jaro_winkler_fuzz_index_{}", std::process::id()));
+    candidate_index.save(&path).unwrap();
+    let opened_index = CandidateIndex::open(&path).unwrap();
+    std::fs::remove_file(&path).ok();
+    for name in &input.names {
+        let matches = candidate_index.matches(name, &MatchOptions::new(0.0)).unwrap();
+        assert!(is_valid_name(name) || matches.is_empty(), "invalid name {:?} matched", name);
+        assert!(matches.iter().all(|&(_, jw)| (0.0..=1.0).contains(&jw)), "{:?} scored outside [0, 1]: {:?}", name, matches);
+        let mut opened_matches = opened_index.matches(name, &MatchOptions::new(0.0)).unwrap();
+        let mut matches = matches;
+        matches.sort_by_key(|&(b_id, _)| b_id);
+        opened_matches.sort_by_key(|&(b_id, _)| b_id);
+        assert_eq!(matches, opened_matches, "{:?} matched differently once saved", name);
+    }
+});
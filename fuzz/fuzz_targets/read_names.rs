@@ -0,0 +1,17 @@
+// This file is part of the IPUMS's pseudo_jaro_winkler.
+// For copyright and licensing information, see the NOTICE and LICENSE files
+// in this project's top-level directory, and also on-line at:
+//   https://github.com/ipums/pseudo_jaro_winkler
+
+//! Reads any file of names the way the command line program does, making sure that nothing panics
+//! and that only valid names are read, one per line, up to the first invalid one.
+
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use pseudo_jaro_winkler::{is_valid_name, read_names};
+
+fuzz_target!(|data: &[u8]| {
+    let names = read_names(data).take_while(Result::is_ok).map(Result::unwrap).collect::<Vec<String>>();
+    assert!(names.iter().all(|name| is_valid_name(name)), "{:?}", names);
+    assert!(names.len() <= data.iter().filter(|&&c| c == b'\n').count() + 1);
+});
@@ -0,0 +1,49 @@
+// This file is part of the IPUMS's pseudo_jaro_winkler.
+// For copyright and licensing information, see the NOTICE and LICENSE files
+// in this project's top-level directory, and also on-line at:
+//   https://github.com/ipums/pseudo_jaro_winkler
+
+//! Scores any query against any candidates with any options, making sure that nothing panics,
+//! every score is within [0, 1] and reaches the threshold, and every kernel and strategy scores
+//! the same.
+
+#![no_main]
+use arbitrary::Arbitrary;
+use libfuzzer_sys::fuzz_target;
+use pseudo_jaro_winkler::{jaro_winkler, CandidateIndex, Kernel, MatchOptions, MatchOrder, ScoringStrategy};
+
+#[derive(Arbitrary, Debug)]
+struct Input {
+    query_name: String,
+    names: Vec<String>,
+    /// The threshold in 1000ths, from 0.0 to 1.1.
+    min_jaro_winkler: u16,
+    top_k: Option<u8>,
+    /// The verify margin in 1000ths.
+    verify_margin: Option<u16>,
+    recall_safe: bool,
+}
+
+fuzz_target!(|input: Input| {
+    let min_jaro_winkler = (input.min_jaro_winkler % 1101) as f32 / 1000.0;
+    let candidate_index = CandidateIndex::new(&input.names);
+    let options = MatchOptions {
+        top_k: input.top_k.map(|top_k| top_k as usize + 1),
+        order: MatchOrder::ByCandidate,
+        verify_margin: input.verify_margin.map(|verify_margin| (verify_margin % 1001) as f32 / 1000.0),
+        recall_safe: input.recall_safe,
+        ..MatchOptions::new(min_jaro_winkler)
+    };
+    let matches = candidate_index.matches(&input.query_name, &options).unwrap();
+    assert!(matches.iter().all(|&(b_id, jw)| b_id < input.names.len() && (0.0..=1.0).contains(&jw) && jw >= min_jaro_winkler), "{:?}", matches);
+    let kernels = [Kernel::Scalar, Kernel::Sse41, Kernel::Avx2];
+    for kernel in kernels.iter().copied().filter(Kernel::is_available) {
+        for strategy in [ScoringStrategy::AllLetters, ScoringStrategy::RareLettersFirst] {
+            assert_eq!(candidate_index.matches(&input.query_name, &MatchOptions { kernel, strategy, ..options.clone() }).unwrap(), matches, "{:?} {:?}", kernel, strategy);
+        }
+    }
+    for name in &input.names {
+        let jw = jaro_winkler(&input.query_name, name);
+        assert!((0.0..=1.0).contains(&jw), "{:?} {:?}: {}", input.query_name, name, jw);
+    }
+});
@@ -0,0 +1,80 @@
+// This file is part of the IPUMS's pseudo_jaro_winkler.
+// For copyright and licensing information, see the NOTICE and LICENSE files
+// in this project's top-level directory, and also on-line at:
+//   https://github.com/ipums/pseudo_jaro_winkler
+
+//! The flat arrays a `CandidateIndex` is made of, which live either on the heap or within a
+//! memory-mapped index file. See `mapped` for the index file format.
+
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::marker::PhantomData;
+use core::ops::Deref;
+use crate::CandidateLetterInfo;
+
+/// Plain data that can be read straight from the bytes of an index file.
+///
+/// # Safety
+///
+/// Every bit pattern must be a valid value, and the type must not have any padding bytes.
+pub(crate) unsafe trait Pod: Copy + Send + Sync + 'static {}
+unsafe impl Pod for u8 {}
+unsafe impl Pod for u16 {}
+unsafe impl Pod for u64 {}
+unsafe impl Pod for [u8; 16] {}
+unsafe impl Pod for [u64; 17] {}
+unsafe impl Pod for CandidateLetterInfo {}
+
+/// An immutable array that lives either on the heap or within a memory-mapped index file. Clones
+/// share the same elements.
+pub(crate) struct Array<T: Pod> {
+    ptr: *const T,
+    len: usize,
+    /// Owns the elements: the `Vec<T>` when they are on the heap, or the mapping of the index
+    /// file when they are within it.
+    _owner: Arc<dyn Send + Sync>,
+    _marker: PhantomData<T>,
+}
+
+// The elements are never modified and are owned by the array, like a `Vec<T>` or `Arc<[T]>`.
+unsafe impl<T: Pod> Send for Array<T> {}
+unsafe impl<T: Pod> Sync for Array<T> {}
+
+impl<T: Pod> From<Vec<T>> for Array<T> {
+    fn from(heap: Vec<T>) -> Array<T> {
+        Array { ptr: heap.as_ptr(), len: heap.len(), _owner: Arc::new(heap), _marker: PhantomData }
+    }
+}
+
+impl<T: Pod> Clone for Array<T> {
+    fn clone(&self) -> Array<T> {
+        Array { ptr: self.ptr, len: self.len, _owner: self._owner.clone(), _marker: PhantomData }
+    }
+}
+
+impl<T: Pod> Deref for Array<T> {
+    type Target = [T];
+
+    #[inline(always)]
+    fn deref(&self) -> &[T] {
+        unsafe { core::slice::from_raw_parts(self.ptr, self.len) }
+    }
+}
+
+impl<T: Pod> Array<T> {
+    /// Views `len` elements owned by `owner` as an array.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must be aligned and point to `len` elements which stay valid and unmodified for as
+    /// long as `owner` is alive.
+    #[cfg(feature = "io")]
+    pub(crate) unsafe fn from_raw_parts(ptr: *const T, len: usize, owner: Arc<dyn Send + Sync>) -> Array<T> {
+        Array { ptr, len, _owner: owner, _marker: PhantomData }
+    }
+
+    #[cfg(feature = "io")]
+    pub(crate) fn as_bytes(&self) -> &[u8] {
+        unsafe { core::slice::from_raw_parts(self.ptr as *const u8, self.len * core::mem::size_of::<T>()) }
+    }
+}
@@ -0,0 +1,58 @@
+// This file is part of the IPUMS's pseudo_jaro_winkler.
+// For copyright and licensing information, see the NOTICE and LICENSE files
+// in this project's top-level directory, and also on-line at:
+//   https://github.com/ipums/pseudo_jaro_winkler
+
+//! Stopping long runs of matching files of names cleanly. The cancellation token and deadline in
+//! `MatchOptions` are checked before matching each name, so a stopped run finishes writing the
+//! names it already started and never leaves a partly written output file behind.
+
+use alloc::sync::Arc;
+#[cfg(feature = "io")]
+use alloc::vec::Vec;
+#[cfg(feature = "io")]
+use core::ops::Range;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+/// Stops a run of matching when cancelled, for instance from a Ctrl-C handler or another thread.
+/// Clones share the same state, so cancelling any of them cancels them all.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+    is_cancelled: Arc<AtomicBool>,
+}
+
+impl CancellationToken {
+    pub fn new() -> CancellationToken {
+        CancellationToken::default()
+    }
+
+    /// Stops every run using this token once the names they are matching are written.
+    pub fn cancel(&self) {
+        self.is_cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.is_cancelled.load(Ordering::Relaxed)
+    }
+}
+
+/// The names of names_a whose output files were written by a run of matching, which is every
+/// name unless the run was stopped by its cancellation token or deadline.
+#[cfg(feature = "io")]
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct MatchOutcome {
+    /// The ranges of indices within names_a of the names whose output files were written in
+    /// full, in increasing order and without gaps or overlaps between adjacent ranges. When
+    /// resuming a stopped run, only the other names need to be matched.
+    pub completed: Vec<Range<usize>>,
+    /// Whether the run was stopped before matching every name.
+    pub is_stopped: bool,
+}
+
+#[cfg(feature = "io")]
+impl MatchOutcome {
+    /// The number of names whose output files were written in full.
+    pub fn completed_count(&self) -> usize {
+        self.completed.iter().map(ExactSizeIterator::len).sum()
+    }
+}
@@ -0,0 +1,299 @@
+// This file is part of the IPUMS's pseudo_jaro_winkler.
+// For copyright and licensing information, see the NOTICE and LICENSE files
+// in this project's top-level directory, and also on-line at:
+//   https://github.com/ipums/pseudo_jaro_winkler
+
+//! Checkpointing runs of matching files of names, so that a run which is stopped or killed can be
+//! resumed without matching the finished names again.
+//!
+//! While checkpointing, each output file is written to a `{a_id}.tmp` file which is renamed to
+//! `{a_id}.txt` once it has been synced to disk, so an output file is never half written, even if
+//! the machine crashes. After every
+//! chunk of names, the names whose output files are in place are recorded in a manifest within the
+//! output directory, which is also written to a temporary file and renamed. The manifest is a text
+//! file with one entry per line:
+//!
+//! * `pseudo_jaro_winkler manifest 1`, the format version.
+//! * `min_jaro_winkler 0.8`, `top_k 10` (or `top_k none`), `order unsorted` (or `by-score` or
+//!   `by-candidate`), `verify_margin 0.02` (or `verify_margin none`), `recall_safe false` (or
+//!   `true`) and `empty_names skip` (or `error` or `score`), which must be the same when resuming.
+//!   A missing order is `unsorted`, a missing margin is `none`, a missing `recall_safe` is
+//!   `false` and a missing `empty_names` is `skip`.
+//! * `names_a 10000` and `candidates 20000`, the number of names matched and the number of
+//!   candidates in the index, so that a run isn't resumed with different input files. `names_a` is
+//!   only written once it is known, which for a stream of names is when the stream ends.
+//! * `completed 0..5000` for each range of names whose output files are written, in order.
+
+use std::fs::{read_to_string, rename, File};
+use std::io::{self, BufWriter, Error, ErrorKind};
+use std::io::prelude::*;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use itertools::Itertools;
+use crate::{EmptyNames, MatchOptions, MatchOrder};
+
+/// The name of the manifest within the output directory.
+pub const MANIFEST_FILE_NAME: &str = "pseudo_jaro_winkler_manifest.txt";
+const HEADER: &str = "pseudo_jaro_winkler manifest 1";
+const ORDERS: [(MatchOrder, &str); 3] = [(MatchOrder::Unsorted, "unsorted"), (MatchOrder::ByScore, "by-score"), (MatchOrder::ByCandidate, "by-candidate")];
+const EMPTY_NAMES: [(EmptyNames, &str); 3] = [(EmptyNames::Skip, "skip"), (EmptyNames::Error, "error"), (EmptyNames::Score, "score")];
+
+/// Whether to checkpoint a run of matching files of names. This applies to
+/// `pseudo_jaro_winkler_with_options`, `pseudo_jaro_winkler_streaming`,
+/// `pseudo_jaro_winkler_with_index` and the sharded functions, which write a manifest into each
+/// shard's directory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Checkpoint {
+    /// Writes the output files in place without a manifest.
+    Off,
+    /// Writes a manifest, starting over even if the output directory already has one.
+    Write,
+    /// Writes a manifest, skipping the names that the output directory's manifest says are
+    /// already written. Starts over if there is no manifest. The output files are the same as if
+    /// the run had never been stopped.
+    Resume,
+}
+
+/// The names whose output files have been written by a run, and the manifest recording them. The
+/// default has no names and is never written.
+#[derive(Debug, Default)]
+pub(crate) struct Manifest {
+    /// Where to write the manifest, or `None` when not checkpointing.
+    path: Option<PathBuf>,
+    min_jaro_winkler: f32,
+    top_k: Option<usize>,
+    order: MatchOrder,
+    verify_margin: Option<f32>,
+    recall_safe: bool,
+    empty_names: EmptyNames,
+    /// The number of names in names_a, if known.
+    names_a: Option<usize>,
+    /// The number of candidates matched against, which is only unknown in a manifest written
+    /// before it was recorded.
+    candidates: Option<usize>,
+    /// The ranges of indices within names_a of the names whose output files are written, in
+    /// increasing order and neither overlapping nor touching.
+    completed: Vec<Range<usize>>,
+}
+
+impl Manifest {
+    /// Starts recording a run into the output directory, reading the names which were already
+    /// written when resuming. Resuming fails if the manifest was written with other options, or
+    /// for a different number of names or candidates.
+    ///
+    /// # Arguments
+    ///
+    /// * `output_dir`: The output directory to write the manifest into.
+    /// * `options`: How the names are scored and written. See `MatchOptions`.
+    /// * `names_a`: The number of names in names_a, if known before reading them.
+    /// * `candidates`: The number of candidates in the index.
+    pub fn start(output_dir: &Path, options: &MatchOptions, names_a: Option<usize>, candidates: usize) -> io::Result<Manifest> {
+        let path = output_dir.join(MANIFEST_FILE_NAME);
+        let mut manifest = Manifest { path: None, min_jaro_winkler: options.min_jaro_winkler, top_k: options.top_k, order: options.order, verify_margin: options.verify_margin, recall_safe: options.recall_safe, empty_names: options.empty_names, names_a, candidates: Some(candidates), completed: Vec::new() };
+        match options.checkpoint {
+            Checkpoint::Off => return Ok(manifest),
+            Checkpoint::Resume if path.exists() => {
+                let resumed = Manifest::read(&path)?;
+                let resumed_options = (resumed.min_jaro_winkler, resumed.top_k, resumed.order, resumed.verify_margin, resumed.recall_safe, resumed.empty_names);
+                let options = (manifest.min_jaro_winkler, manifest.top_k, manifest.order, manifest.verify_margin, manifest.recall_safe, manifest.empty_names);
+                if resumed_options != options {
+                    return Err(Error::new(ErrorKind::InvalidInput, format!(
+                        "{} was written with min_jaro_winkler, top_k, order, verify_margin, recall_safe and empty_names {:?}, not {:?}",
+                        path.display(), resumed_options, options,
+                    )));
+                }
+                if let Some(resumed_candidates) = resumed.candidates.filter(|&resumed_candidates| resumed_candidates != candidates) {
+                    return Err(Error::new(ErrorKind::InvalidInput, format!(
+                        "{} was written for {} candidates, not {}", path.display(), resumed_candidates, candidates,
+                    )));
+                }
+                if let (Some(resumed_names_a), Some(names_a)) = (resumed.names_a, names_a) {
+                    if resumed_names_a != names_a {
+                        return Err(Error::new(ErrorKind::InvalidInput, format!(
+                            "{} was written for {} names in names_a, not {}", path.display(), resumed_names_a, names_a,
+                        )));
+                    }
+                }
+                manifest.names_a = names_a.or(resumed.names_a);
+                manifest.completed = resumed.completed;
+                if let Some(names_a) = manifest.names_a {
+                    manifest.check_names_a(names_a)?;
+                }
+            },
+            _ => {},
+        }
+        manifest.path = Some(path);
+        manifest.write()?;
+        Ok(manifest)
+    }
+
+    /// The number of names that resuming a run in the output directory would skip, which is none
+    /// unless the options say to resume and the directory has a manifest.
+    pub fn resumed_count(output_dir: &Path, options: &MatchOptions) -> io::Result<usize> {
+        let path = output_dir.join(MANIFEST_FILE_NAME);
+        if options.checkpoint != Checkpoint::Resume || !path.exists() {
+            return Ok(0);
+        }
+        Ok(Manifest::read(&path)?.completed.iter().map(ExactSizeIterator::len).sum())
+    }
+
+    fn read(path: &Path) -> io::Result<Manifest> {
+        let invalid_data = |line: &str| Error::new(ErrorKind::InvalidData, format!("Invalid manifest {}: {:?}", path.display(), line));
+        let contents = read_to_string(path)?;
+        let mut lines = contents.lines();
+        if lines.next() != Some(HEADER) {
+            return Err(invalid_data(contents.lines().next().unwrap_or("")));
+        }
+        let mut manifest = Manifest { path: None, min_jaro_winkler: 0.0, top_k: None, order: MatchOrder::Unsorted, verify_margin: None, recall_safe: false, empty_names: EmptyNames::Skip, names_a: None, candidates: None, completed: Vec::new() };
+        for line in lines {
+            let (key, value) = line.split_once(' ').ok_or_else(|| invalid_data(line))?;
+            match key {
+                "min_jaro_winkler" => manifest.min_jaro_winkler = value.parse().map_err(|_| invalid_data(line))?,
+                "top_k" if value == "none" => manifest.top_k = None,
+                "top_k" => manifest.top_k = Some(value.parse().map_err(|_| invalid_data(line))?),
+                "verify_margin" if value == "none" => manifest.verify_margin = None,
+                "verify_margin" => manifest.verify_margin = Some(value.parse().map_err(|_| invalid_data(line))?),
+                "recall_safe" => manifest.recall_safe = value.parse().map_err(|_| invalid_data(line))?,
+                "order" => manifest.order = ORDERS.iter().find(|(_, name)| *name == value).ok_or_else(|| invalid_data(line))?.0,
+                "empty_names" => manifest.empty_names = EMPTY_NAMES.iter().find(|(_, name)| *name == value).ok_or_else(|| invalid_data(line))?.0,
+                "names_a" => manifest.names_a = Some(value.parse().map_err(|_| invalid_data(line))?),
+                "candidates" => manifest.candidates = Some(value.parse().map_err(|_| invalid_data(line))?),
+                "completed" => {
+                    let (start, end) = value.split_once("..").ok_or_else(|| invalid_data(line))?;
+                    let start = start.parse::<usize>().map_err(|_| invalid_data(line))?;
+                    let end = end.parse::<usize>().map_err(|_| invalid_data(line))?;
+                    manifest.completed.push(start..end);
+                },
+                _ => return Err(invalid_data(line)),
+            }
+        }
+        if !manifest.completed.windows(2).all(|pair| pair[0].end < pair[1].start) || manifest.completed.iter().any(|range| range.is_empty()) {
+            return Err(invalid_data("completed ranges out of order"));
+        }
+        Ok(manifest)
+    }
+
+    fn write(&self) -> io::Result<()> {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+        write_file(path, true, |file| {
+            writeln!(file, "{}", HEADER)?;
+            writeln!(file, "min_jaro_winkler {}", self.min_jaro_winkler)?;
+            match self.top_k {
+                Some(top_k) => writeln!(file, "top_k {}", top_k)?,
+                None => writeln!(file, "top_k none")?,
+            }
+            writeln!(file, "order {}", ORDERS.iter().find(|(order, _)| *order == self.order).unwrap().1)?;
+            match self.verify_margin {
+                Some(verify_margin) => writeln!(file, "verify_margin {}", verify_margin)?,
+                None => writeln!(file, "verify_margin none")?,
+            }
+            writeln!(file, "recall_safe {}", self.recall_safe)?;
+            writeln!(file, "empty_names {}", EMPTY_NAMES.iter().find(|(empty_names, _)| *empty_names == self.empty_names).unwrap().1)?;
+            if let Some(names_a) = self.names_a {
+                writeln!(file, "names_a {}", names_a)?;
+            }
+            if let Some(candidates) = self.candidates {
+                writeln!(file, "candidates {}", candidates)?;
+            }
+            self.completed.iter().try_for_each(|range| writeln!(file, "completed {}..{}", range.start, range.end))
+        })
+    }
+
+    pub fn is_completed(&self, a_id: usize) -> bool {
+        let after = self.completed.partition_point(|range| range.start <= a_id);
+        after > 0 && self.completed[after - 1].contains(&a_id)
+    }
+
+    /// The ranges of indices within names_a of the names whose output files are written, in
+    /// increasing order.
+    pub fn completed(&self) -> Vec<Range<usize>> {
+        self.completed.clone()
+    }
+
+    /// Checks that names_a has at least `names_read` names, when it is known how many it has.
+    /// Fails when resuming a run with more names in names_a than the manifest was written for.
+    pub fn check_names_read(&self, names_read: usize) -> io::Result<()> {
+        match (&self.path, self.names_a) {
+            (Some(path), Some(names_a)) if names_read > names_a => Err(Error::new(ErrorKind::InvalidInput, format!(
+                "{} was written for {} names in names_a, not at least {}", path.display(), names_a, names_read,
+            ))),
+            _ => Ok(()),
+        }
+    }
+
+    /// Records that names_a has ended after `names_read` names. Fails when resuming a run with
+    /// fewer names in names_a than the manifest was written for, or than it says were completed.
+    pub fn finish_names_a(&mut self, names_read: usize) -> io::Result<()> {
+        self.check_names_a(names_read)?;
+        if self.names_a != Some(names_read) {
+            self.names_a = Some(names_read);
+            self.write()?;
+        }
+        Ok(())
+    }
+
+    /// Checks that names_a has `names_a` names, when checkpointing.
+    fn check_names_a(&self, names_a: usize) -> io::Result<()> {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+        let completed_end = self.completed.last().map_or(0, |range| range.end);
+        if (self.names_a.is_some() && self.names_a != Some(names_a)) || completed_end > names_a {
+            return Err(Error::new(ErrorKind::InvalidInput, format!(
+                "{} was written for {} names in names_a, with {} completed, not {}", path.display(), self.names_a.map_or("unknown".to_string(), |recorded| recorded.to_string()), completed_end, names_a,
+            )));
+        }
+        Ok(())
+    }
+
+    /// Records that the output files of more names have been written.
+    ///
+    /// # Arguments
+    ///
+    /// * `completed`: The indices within names_a of the names, in increasing order.
+    pub fn record(&mut self, completed: &[usize]) -> io::Result<()> {
+        if completed.is_empty() {
+            return Ok(());
+        }
+        let new_ranges = completed.iter().enumerate().group_by(|&(i, &a_id)| a_id - i).into_iter().map(|(_, mut range)| {
+            let start = *range.next().unwrap().1;
+            start..range.last().map_or(start, |(_, &a_id)| a_id) + 1
+        }).collect::<Vec<_>>();
+        self.completed = self.completed.drain(..).merge_by(new_ranges, |a, b| a.start <= b.start).coalesce(|a, b| {
+            if b.start <= a.end { Ok(a.start..a.end.max(b.end)) } else { Err((a, b)) }
+        }).collect();
+        self.write()
+    }
+}
+
+/// Writes a file, checking that every byte made it out of the buffer. When `is_atomic`, the file is
+/// written to a temporary file next to it, synced to disk and then renamed, and the rename is
+/// synced too, so that the file is either missing or complete even if the process is killed or the
+/// machine crashes.
+pub(crate) fn write_file<F>(path: &Path, is_atomic: bool, write: F) -> io::Result<()>
+where F: FnOnce(&mut BufWriter<File>) -> io::Result<()> {
+    let temp_path = if is_atomic { path.with_extension("tmp") } else { path.to_path_buf() };
+    let mut file = BufWriter::with_capacity(100000, File::create(&temp_path)?);
+    write(&mut file)?;
+    let file = file.into_inner().map_err(|e| e.into_error())?;
+    if is_atomic {
+        file.sync_all()?;
+        rename(&temp_path, path)?;
+        sync_dir(path.parent().filter(|dir| !dir.as_os_str().is_empty()).unwrap_or_else(|| Path::new(".")))?;
+    }
+    Ok(())
+}
+
+/// Syncs a directory to disk, so that the files renamed into it are kept after a crash. Directories
+/// can only be opened to be synced on unix.
+fn sync_dir(dir: &Path) -> io::Result<()> {
+    #[cfg(unix)]
+    File::open(dir)?.sync_all()?;
+    #[cfg(not(unix))]
+    let _ = dir;
+    Ok(())
+}
@@ -0,0 +1,234 @@
+// This file is part of the IPUMS's pseudo_jaro_winkler.
+// For copyright and licensing information, see the NOTICE and LICENSE files
+// in this project's top-level directory, and also on-line at:
+//   https://github.com/ipums/pseudo_jaro_winkler
+
+//! Measuring how close the pseudo scores are to the exact jaro winkler on a sample of names, to
+//! judge whether the approximation is good enough for a new dataset before matching all of it.
+
+use std::fmt;
+use rayon::prelude::*;
+use crate::{jaro_winkler, CandidateIndex, MatchOptions};
+
+/// The upper bounds of the buckets of the error histogram. The last bucket holds every error of at
+/// least 0.2.
+pub const ERROR_BUCKETS: [f64; 6] = [0.001, 0.01, 0.02, 0.05, 0.1, 0.2];
+
+/// Options for evaluating the pseudo scores.
+#[derive(Debug, Clone)]
+pub struct EvaluateOptions {
+    /// The threshold to measure the precision and recall of the pseudo scores at.
+    pub min_jaro_winkler: f32,
+    /// Only the pairs where either score reaches this are counted in the errors, since the many
+    /// pairs of unrelated names would otherwise hide the errors which matter.
+    pub min_error_score: f64,
+    /// The number of pairs with the largest errors to report.
+    pub worst_count: usize,
+}
+
+impl EvaluateOptions {
+    /// Creates options with the given threshold which count the errors of the pairs scoring at
+    /// least 0.7, and report the 10 worst pairs.
+    pub fn new(min_jaro_winkler: f32) -> EvaluateOptions {
+        EvaluateOptions { min_jaro_winkler, min_error_score: 0.7, worst_count: 10 }
+    }
+}
+
+/// A pair of names with both of their scores.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScoredPair {
+    /// The index of the name within names_a.
+    pub a_id: usize,
+    /// The index of the name within names_b.
+    pub b_id: usize,
+    pub name_a: String,
+    pub name_b: String,
+    /// The pseudo score, or 0.0 if the names share no letters.
+    pub pseudo: f32,
+    /// The exact jaro winkler. See `jaro_winkler`.
+    pub exact: f64,
+}
+
+impl ScoredPair {
+    pub fn error(&self) -> f64 {
+        (self.pseudo as f64 - self.exact).abs()
+    }
+}
+
+/// How close the pseudo scores of every pair of names are to the exact jaro winklers. See
+/// `evaluate`. Its `Display` is a report for people.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Evaluation {
+    pub options_min_jaro_winkler: f32,
+    pub options_min_error_score: f64,
+    pub name_count_a: usize,
+    pub name_count_b: usize,
+    /// The number of pairs counted in the errors.
+    pub error_count: usize,
+    pub mean_error: f64,
+    pub std_dev_error: f64,
+    pub max_error: f64,
+    /// The number of errors in each bucket of `ERROR_BUCKETS`, and then of at least the last
+    /// bound.
+    pub histogram: Vec<usize>,
+    /// The number of pairs where both scores reach the threshold.
+    pub true_positives: usize,
+    /// The number of pairs where only the pseudo score reaches the threshold.
+    pub false_positives: usize,
+    /// The number of pairs where only the exact score reaches the threshold.
+    pub false_negatives: usize,
+    /// The pairs with the largest errors, from largest to smallest.
+    pub worst_pairs: Vec<ScoredPair>,
+}
+
+impl Evaluation {
+    /// The fraction of the pairs whose pseudo score reaches the threshold that truly reach it, or
+    /// 1.0 if there are none.
+    pub fn precision(&self) -> f64 {
+        fraction(self.true_positives, self.true_positives + self.false_positives)
+    }
+
+    /// The fraction of the pairs which truly reach the threshold that the pseudo score finds, or
+    /// 1.0 if there are none.
+    pub fn recall(&self) -> f64 {
+        fraction(self.true_positives, self.true_positives + self.false_negatives)
+    }
+}
+
+fn fraction(count: usize, total: usize) -> f64 {
+    if total == 0 { 1.0 } else { count as f64 / total as f64 }
+}
+
+/// The running totals for the names scored by one thread.
+#[derive(Default)]
+struct Totals {
+    error_count: usize,
+    error_sum: f64,
+    error_square_sum: f64,
+    max_error: f64,
+    histogram: [usize; ERROR_BUCKETS.len() + 1],
+    true_positives: usize,
+    false_positives: usize,
+    false_negatives: usize,
+    /// The pairs with the largest errors so far, as indices within names_a and names_b and the
+    /// pseudo and exact scores. The names are only looked up for the final worst pairs.
+    worst_pairs: Vec<(usize, usize, f32, f64)>,
+}
+
+impl Totals {
+    fn add(&mut self, (a_id, b_id, pseudo, exact): (usize, usize, f32, f64), options: &EvaluateOptions) {
+        let is_pseudo_match = pseudo >= options.min_jaro_winkler;
+        let is_exact_match = exact as f32 >= options.min_jaro_winkler;
+        match (is_pseudo_match, is_exact_match) {
+            (true, true) => self.true_positives += 1,
+            (true, false) => self.false_positives += 1,
+            (false, true) => self.false_negatives += 1,
+            (false, false) => {},
+        }
+        if (pseudo as f64) < options.min_error_score && exact < options.min_error_score {
+            return;
+        }
+        let error = (pseudo as f64 - exact).abs();
+        self.error_count += 1;
+        self.error_sum += error;
+        self.error_square_sum += error * error;
+        self.max_error = self.max_error.max(error);
+        self.histogram[ERROR_BUCKETS.partition_point(|&bound| bound <= error)] += 1;
+        if options.worst_count > 0 {
+            self.worst_pairs.push((a_id, b_id, pseudo, exact));
+            if self.worst_pairs.len() >= options.worst_count * 2 {
+                self.keep_worst(options.worst_count);
+            }
+        }
+    }
+
+    fn merge(mut self, other: Totals, options: &EvaluateOptions) -> Totals {
+        self.error_count += other.error_count;
+        self.error_sum += other.error_sum;
+        self.error_square_sum += other.error_square_sum;
+        self.max_error = self.max_error.max(other.max_error);
+        self.histogram.iter_mut().zip(other.histogram).for_each(|(count, other_count)| *count += other_count);
+        self.true_positives += other.true_positives;
+        self.false_positives += other.false_positives;
+        self.false_negatives += other.false_negatives;
+        self.worst_pairs.extend(other.worst_pairs);
+        self.keep_worst(options.worst_count);
+        self
+    }
+
+    /// Keeps the pairs with the largest errors, breaking ties by the names' indices so that the
+    /// result doesn't depend on how the names were split between threads.
+    fn keep_worst(&mut self, worst_count: usize) {
+        let error = |&(_, _, pseudo, exact): &(usize, usize, f32, f64)| (pseudo as f64 - exact).abs();
+        self.worst_pairs.sort_by(|a, b| error(b).total_cmp(&error(a)).then((a.0, a.1).cmp(&(b.0, b.1))));
+        self.worst_pairs.truncate(worst_count);
+    }
+}
+
+/// Scores every name of names_a against every name of names_b with both the pseudo score and the
+/// exact jaro winkler, and measures how far apart they are. This scores every pair exactly, so use
+/// a sample of a few hundred or thousand names from names_a.
+///
+/// # Arguments
+///
+/// * `names_a`: The names to score, such as a sample of the names to match. They must be valid,
+///   see `is_valid_name`.
+/// * `names_b`: The candidates to score them against, which must also be valid.
+/// * `options`: The threshold and how to report the errors. See `EvaluateOptions`.
+pub fn evaluate(names_a: &[String], names_b: &[String], options: &EvaluateOptions) -> Evaluation {
+    let candidate_index = CandidateIndex::new(names_b);
+    let match_options = MatchOptions::new(0.0);
+    let totals = names_a.par_iter().enumerate().fold(Totals::default, |mut totals, (a_id, name_a)| {
+        let mut pseudo_scores = vec![0.0; names_b.len()];
+        // Empty names are skipped, which never fails
+        candidate_index.matches(name_a, &match_options).unwrap_or_default().into_iter().for_each(|(b_id, jw)| pseudo_scores[b_id] = jw);
+        names_b.iter().zip(pseudo_scores).enumerate().for_each(|(b_id, (name_b, pseudo))| {
+            totals.add((a_id, b_id, pseudo, jaro_winkler(name_a, name_b)), options);
+        });
+        totals
+    }).reduce(Totals::default, |a, b| a.merge(b, options));
+    let mean_error = if totals.error_count == 0 { 0.0 } else { totals.error_sum / totals.error_count as f64 };
+    let variance = if totals.error_count == 0 { 0.0 } else { totals.error_square_sum / totals.error_count as f64 - mean_error * mean_error };
+    Evaluation {
+        options_min_jaro_winkler: options.min_jaro_winkler,
+        options_min_error_score: options.min_error_score,
+        name_count_a: names_a.len(),
+        name_count_b: names_b.len(),
+        error_count: totals.error_count,
+        mean_error,
+        std_dev_error: variance.max(0.0).sqrt(),
+        max_error: totals.max_error,
+        histogram: totals.histogram.to_vec(),
+        true_positives: totals.true_positives,
+        false_positives: totals.false_positives,
+        false_negatives: totals.false_negatives,
+        worst_pairs: totals.worst_pairs.into_iter().map(|(a_id, b_id, pseudo, exact)| {
+            ScoredPair { a_id, b_id, name_a: names_a[a_id].clone(), name_b: names_b[b_id].clone(), pseudo, exact }
+        }).collect(),
+    }
+}
+
+impl fmt::Display for Evaluation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Scored {} names against {} candidates", self.name_count_a, self.name_count_b)?;
+        writeln!(f, "Errors of the {} pairs where either score is at least {}:", self.error_count, self.options_min_error_score)?;
+        writeln!(f, "  mean {:.4}, standard deviation {:.4}, max {:.4}", self.mean_error, self.std_dev_error, self.max_error)?;
+        let lower_bounds = core::iter::once(0.0).chain(ERROR_BUCKETS);
+        let upper_bounds = ERROR_BUCKETS.iter().map(|&bound| Some(bound)).chain(core::iter::once(None));
+        for ((lower_bound, upper_bound), &count) in lower_bounds.zip(upper_bounds).zip(&self.histogram) {
+            let range = match upper_bound {
+                Some(upper_bound) => format!("{} to {}", lower_bound, upper_bound),
+                None => format!("{} and over", lower_bound),
+            };
+            writeln!(f, "  {:<14} {:>10} {:>7.2}%", range, count, 100.0 * fraction(count, self.error_count))?;
+        }
+        writeln!(
+            f, "At a threshold of {}: precision {:.4}, recall {:.4} ({} true positives, {} false positives, {} false negatives)",
+            self.options_min_jaro_winkler, self.precision(), self.recall(), self.true_positives, self.false_positives, self.false_negatives,
+        )?;
+        writeln!(f, "Worst pairs (name_a, name_b, index in names_a, index in names_b, pseudo score, exact score):")?;
+        self.worst_pairs.iter().try_for_each(|pair| {
+            writeln!(f, "  {},{},{},{},{:.4},{:.4}", pair.name_a, pair.name_b, pair.a_id, pair.b_id, pair.pseudo, pair.exact)
+        })
+    }
+}
@@ -0,0 +1,69 @@
+// This file is part of the IPUMS's pseudo_jaro_winkler.
+// For copyright and licensing information, see the NOTICE and LICENSE files
+// in this project's top-level directory, and also on-line at:
+//   https://github.com/ipums/pseudo_jaro_winkler
+
+//! An exact jaro winkler, for verifying the pseudo scores which are close to the threshold. See
+//! `MatchOptions::verify_margin`.
+//!
+//! "Exact" means exactly the scores of `strsim::jaro_winkler` in strsim 0.10, which the pseudo
+//! scores are tested against and which the pseudo scores approximate, quirks included. It isn't
+//! the textbook jaro winkler, and differs from it in two ways:
+//!
+//! * Transpositions: each letter of the first name is matched to the first unmatched equal letter
+//!   of the second within the matching distance, and a transposition is counted whenever a letter
+//!   matches before the previous match. The textbook jaro winkler instead counts half of the
+//!   matched letters which differ when both names' matched letters are lined up in order.
+//! * The prefix bonus: every letter of the common prefix adds to the score, rather than at most 4
+//!   of them, and the score is then capped at 1.0.
+//!
+//! So two names can score differently here than with other jaro winkler libraries, or with later
+//! versions of strsim.
+
+use alloc::vec;
+
+/// The jaro winkler similarity of two names, from 0.0 to 1.0, scored exactly as strsim 0.10's
+/// `jaro_winkler` does rather than as the textbook jaro winkler: transpositions are counted
+/// differently and the prefix bonus isn't limited to 4 letters. See the module documentation. The
+/// names are compared byte by byte, which is the same as comparing letters for the lowercase
+/// letters and spaces of valid names.
+pub fn jaro_winkler(a: &str, b: &str) -> f64 {
+    let jaro = jaro(a.as_bytes(), b.as_bytes());
+    let prefix_len = a.bytes().zip(b.bytes()).take_while(|(a, b)| a == b).count();
+    (jaro + 0.1 * prefix_len as f64 * (1.0 - jaro)).min(1.0)
+}
+
+fn jaro(a: &[u8], b: &[u8]) -> f64 {
+    if a.is_empty() && b.is_empty() {
+        return 1.0;
+    } else if a.is_empty() || b.is_empty() {
+        return 0.0;
+    } else if a.len() == 1 && b.len() == 1 {
+        return if a == b { 1.0 } else { 0.0 };
+    }
+    let search_range = a.len().max(b.len()) / 2 - 1;
+    let mut b_matched = vec![false; b.len()];
+    let mut matches = 0;
+    let mut transpositions = 0;
+    let mut last_b_index = 0;
+    for (i, a_letter) in a.iter().enumerate() {
+        let min_j = i.saturating_sub(search_range);
+        let max_j = (b.len() - 1).min(i + search_range);
+        if min_j > max_j {
+            continue;
+        }
+        if let Some(j) = (min_j..=max_j).find(|&j| b[j] == *a_letter && !b_matched[j]) {
+            b_matched[j] = true;
+            matches += 1;
+            if j < last_b_index {
+                transpositions += 1;
+            }
+            last_b_index = j;
+        }
+    }
+    if matches == 0 {
+        return 0.0;
+    }
+    let matches = matches as f64;
+    (1.0 / 3.0) * (matches / a.len() as f64 + matches / b.len() as f64 + (matches - transpositions as f64) / matches)
+}
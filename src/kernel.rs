@@ -0,0 +1,311 @@
+// This file is part of the IPUMS's pseudo_jaro_winkler.
+// For copyright and licensing information, see the NOTICE and LICENSE files
+// in this project's top-level directory, and also on-line at:
+//   https://github.com/ipums/pseudo_jaro_winkler
+
+//! SIMD kernels that score a letter of a query against many candidates at once.
+//!
+//! The kernels work on a structure of arrays layout, where every candidate is a lane. Each letter
+//! has a dense array of candidate masks with a mask for every candidate (zero if the candidate
+//! doesn't contain the letter), so a block of candidates can be loaded and updated with a handful
+//! of instructions. The arrays are padded to a multiple of `LANES` so that the kernels never need
+//! to handle a partial block.
+
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use crate::{letter_masks, min_matches_by_candidate_len, CandidateScore};
+use crate::array::Array;
+
+/// Whether the CPU this is running on supports an x86 instruction set extension. Without the
+/// standard library it can't be detected while running, so only the extensions enabled when
+/// compiling are used.
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+macro_rules! has_x86_feature {
+    ($feature:tt) => {{
+        #[cfg(feature = "std")]
+        let has_feature = is_x86_feature_detected!($feature);
+        #[cfg(not(feature = "std"))]
+        let has_feature = cfg!(target_feature = $feature);
+        has_feature
+    }};
+}
+
+/// The number of candidates in a block, which is the number of 16 bit lanes in an AVX2 register.
+pub(crate) const LANES: usize = 16;
+
+/// The instruction set used to score candidates.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kernel {
+    /// Scores one candidate at a time using the per letter lookup lists. Runs on any CPU.
+    Scalar,
+    /// Scores 8 candidates per instruction using SSE4.1.
+    Sse41,
+    /// Scores 16 candidates per instruction using AVX2.
+    Avx2,
+}
+
+impl Kernel {
+    /// Finds the fastest kernel supported by the CPU this is running on.
+    pub fn detect() -> Kernel {
+        [Kernel::Avx2, Kernel::Sse41].iter().copied().find(|kernel| kernel.is_available()).unwrap_or(Kernel::Scalar)
+    }
+
+    /// Whether the CPU this is running on supports the kernel.
+    pub fn is_available(&self) -> bool {
+        match self {
+            Kernel::Scalar => true,
+            #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+            Kernel::Sse41 => has_x86_feature!("sse4.1"),
+            #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+            Kernel::Avx2 => has_x86_feature!("avx2"),
+            #[cfg(not(any(target_arch = "x86", target_arch = "x86_64")))]
+            _ => false,
+        }
+    }
+}
+
+/// Whether the CPU this is running on supports the x86-64-v3 instructions that the multiversioned
+/// functions are compiled for.
+#[cfg(all(feature = "std", any(target_arch = "x86", target_arch = "x86_64")))]
+pub(crate) fn has_x86_64_v3() -> bool {
+    is_x86_feature_detected!("avx2") && is_x86_feature_detected!("bmi1") && is_x86_feature_detected!("bmi2")
+        && is_x86_feature_detected!("fma") && is_x86_feature_detected!("lzcnt") && is_x86_feature_detected!("popcnt")
+}
+
+/// Without the standard library the CPU can't be checked, so the multiversioned functions are
+/// never used.
+#[cfg(all(not(feature = "std"), any(target_arch = "x86", target_arch = "x86_64")))]
+pub(crate) fn has_x86_64_v3() -> bool {
+    false
+}
+
+/// Score cards for every candidate, stored as a structure of arrays. Each array holds the field of
+/// the same name in `CandidateScore` for every candidate, widened to 16 bits so that every field
+/// fits the same number of candidates in a register.
+#[derive(Clone)]
+pub(crate) struct CandidateScores {
+    matches: Vec<u16>,
+    used: Vec<u16>,
+    used_exact: Vec<u16>,
+    last_match_letter_index: Vec<u16>,
+    transposition_count: Vec<u16>,
+}
+
+impl CandidateScores {
+    #[inline]
+    fn new(len: usize) -> CandidateScores {
+        CandidateScores { matches: vec![0; len], used: vec![0; len], used_exact: vec![0; len], last_match_letter_index: vec![0; len], transposition_count: vec![0; len] }
+    }
+
+    /// Gets the score card for a single candidate.
+    #[inline]
+    fn get(&self, i: usize, len_partial: u16) -> CandidateScore {
+        CandidateScore {
+            matches: self.matches[i] as u8,
+            used: self.used[i],
+            used_exact: self.used_exact[i],
+            len_partial,
+            last_match_letter_index: self.last_match_letter_index[i],
+            transposition_count: self.transposition_count[i] as u8,
+        }
+    }
+}
+
+/// The candidates in the layout used by the SIMD kernels.
+#[derive(Clone)]
+pub(crate) struct DenseCandidates {
+    /// The number of candidates, not including padding.
+    pub(crate) len: usize,
+    /// The mask of every candidate for each letter, indexed the same way as `letter_masks`, one
+    /// letter after another.
+    pub(crate) letter_masks: Array<u16>,
+    /// The length of each candidate minus one, for looking up the query mask to use.
+    pub(crate) len_indices: Array<u8>,
+    /// The `len_partial` of each candidate. See `CandidateScore`.
+    pub(crate) len_partials: Array<u16>,
+}
+
+impl DenseCandidates {
+    pub(crate) fn new(names: &[String]) -> DenseCandidates {
+        let padded_len = names.len().div_ceil(LANES) * LANES;
+        let mut dense_letter_masks = vec![0; 27 * padded_len];
+        let mut len_indices = vec![0; padded_len];
+        let mut len_partials = vec![0; padded_len];
+        names.iter().enumerate().for_each(|(name_index, name)| {
+            letter_masks(name).iter().enumerate().for_each(|(letter_index, &mask)| {
+                dense_letter_masks[letter_index * padded_len + name_index] = mask;
+            });
+            len_indices[name_index] = (name.len() - 1) as u8;
+            len_partials[name_index] = CandidateScore::new(name.len() as u8).len_partial;
+        });
+        DenseCandidates { len: names.len(), letter_masks: dense_letter_masks.into(), len_indices: len_indices.into(), len_partials: len_partials.into() }
+    }
+
+    /// Scores a query against every candidate, returning the index and score of every candidate at
+    /// or above `min_jaro_winkler`.
+    ///
+    /// # Safety
+    ///
+    /// The CPU must support `kernel`.
+    #[inline(always)]
+    pub(crate) unsafe fn score(&self, query_masks_lookup: &[(u8, [u16; 16])], query_partial: u16, min_jaro_winkler: f32, kernel: Kernel) -> Vec<(usize, f32)> {
+        let padded_len = self.len_indices.len();
+        let mut scores = CandidateScores::new(padded_len);
+        for (query_index, (letter_index, query_mask_by_candidate_len)) in query_masks_lookup.iter().enumerate() {
+            let letter_start = *letter_index as usize * padded_len;
+            let candidate_masks = &self.letter_masks[letter_start..letter_start + padded_len];
+            match kernel {
+                #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+                Kernel::Avx2 => x86::score_letter_avx2(&mut scores, query_mask_by_candidate_len, candidate_masks, &self.len_indices, query_index),
+                #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+                Kernel::Sse41 => x86::score_letter_sse41(&mut scores, query_mask_by_candidate_len, candidate_masks, &self.len_indices, query_index),
+                _ => score_letter_lanes(&mut scores, query_mask_by_candidate_len, candidate_masks, &self.len_indices, query_index),
+            }
+        }
+        let min_matches = min_matches_by_candidate_len(query_masks_lookup.len(), min_jaro_winkler);
+        (0..self.len).filter(|&score_i| {
+            scores.matches[score_i] >= min_matches[self.len_indices[score_i] as usize]
+        }).flat_map(|score_i| {
+            let jw = scores.get(score_i, self.len_partials[score_i]).calculate_jaro_winkler(query_partial);
+            if jw >= min_jaro_winkler {
+                Some((score_i, jw))
+            } else { None}
+        }).collect()
+    }
+}
+
+/// A lane by lane version of the SIMD kernels, which is the same as calling `score_letter` on
+/// every candidate. It is written using only operations with an equivalent SIMD instruction so
+/// that it can serve as the reference for the kernels.
+#[inline(always)]
+fn score_letter_lanes(scores: &mut CandidateScores, query_mask_by_candidate_len: &[u16; 16], candidate_masks: &[u16], len_indices: &[u8], query_index: usize) {
+    let exact_bit = 1u16.wrapping_shl(query_index as u32);
+    for i in 0..candidate_masks.len() {
+        let whole_mask_result = query_mask_by_candidate_len[len_indices[i] as usize] & candidate_masks[i];
+        let check_used_result = whole_mask_result & !scores.used[i];
+        let mask_result = check_used_result & check_used_result.wrapping_neg();
+        scores.used[i] |= mask_result;
+        scores.used_exact[i] |= mask_result & exact_bit;
+        scores.matches[i] += (mask_result != 0) as u16;
+        scores.transposition_count[i] += (mask_result.wrapping_sub(1) < scores.last_match_letter_index[i]) as u16;
+        scores.last_match_letter_index[i] |= mask_result;
+    }
+}
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+mod x86 {
+    #[cfg(target_arch = "x86")]
+    use core::arch::x86::*;
+    #[cfg(target_arch = "x86_64")]
+    use core::arch::x86_64::*;
+    use super::{CandidateScores, LANES};
+
+    /// Splits the query masks into a table of their low bytes and a table of their high bytes, so
+    /// that they can be looked up by candidate length with a byte shuffle.
+    fn byte_tables(query_mask_by_candidate_len: &[u16; 16]) -> ([u8; 16], [u8; 16]) {
+        let mut low_bytes = [0; 16];
+        let mut high_bytes = [0; 16];
+        query_mask_by_candidate_len.iter().enumerate().for_each(|(i, query_mask)| {
+            low_bytes[i] = *query_mask as u8;
+            high_bytes[i] = (*query_mask >> 8) as u8;
+        });
+        (low_bytes, high_bytes)
+    }
+
+    /// Scores 16 candidates at a time. See `score_letter_lanes`.
+    #[target_feature(enable = "avx2")]
+    pub(super) unsafe fn score_letter_avx2(scores: &mut CandidateScores, query_mask_by_candidate_len: &[u16; 16], candidate_masks: &[u16], len_indices: &[u8], query_index: usize) {
+        let (low_bytes, high_bytes) = byte_tables(query_mask_by_candidate_len);
+        let low_table = _mm256_broadcastsi128_si256(_mm_loadu_si128(low_bytes.as_ptr() as *const __m128i));
+        let high_table = _mm256_broadcastsi128_si256(_mm_loadu_si128(high_bytes.as_ptr() as *const __m128i));
+        let low_byte_mask = _mm256_set1_epi16(0xff);
+        let exact_bit = _mm256_set1_epi16(1u16.wrapping_shl(query_index as u32) as i16);
+        let ones = _mm256_set1_epi16(1);
+        let zeros = _mm256_setzero_si256();
+        let sign_bit = _mm256_set1_epi16(i16::MIN);
+        for i in (0..candidate_masks.len()).step_by(LANES) {
+            let candidate_mask = _mm256_loadu_si256(candidate_masks.as_ptr().add(i) as *const __m256i);
+            if _mm256_testz_si256(candidate_mask, candidate_mask) == 1 {
+                continue;
+            }
+            // Look up the query mask for each candidate's length, one byte at a time
+            let len_index = _mm256_cvtepu8_epi16(_mm_loadu_si128(len_indices.as_ptr().add(i) as *const __m128i));
+            let low_query_mask = _mm256_and_si256(_mm256_shuffle_epi8(low_table, len_index), low_byte_mask);
+            let high_query_mask = _mm256_slli_epi16(_mm256_shuffle_epi8(high_table, len_index), 8);
+            let query_mask = _mm256_or_si256(low_query_mask, high_query_mask);
+
+            let used_ptr = scores.used.as_mut_ptr().add(i) as *mut __m256i;
+            let used_exact_ptr = scores.used_exact.as_mut_ptr().add(i) as *mut __m256i;
+            let matches_ptr = scores.matches.as_mut_ptr().add(i) as *mut __m256i;
+            let transposition_count_ptr = scores.transposition_count.as_mut_ptr().add(i) as *mut __m256i;
+            let last_match_letter_index_ptr = scores.last_match_letter_index.as_mut_ptr().add(i) as *mut __m256i;
+            let used = _mm256_loadu_si256(used_ptr);
+            let last_match_letter_index = _mm256_loadu_si256(last_match_letter_index_ptr);
+
+            let whole_mask_result = _mm256_and_si256(query_mask, candidate_mask);
+            let check_used_result = _mm256_andnot_si256(used, whole_mask_result);
+            let mask_result = _mm256_and_si256(check_used_result, _mm256_sub_epi16(zeros, check_used_result));
+            let is_match = _mm256_andnot_si256(_mm256_cmpeq_epi16(mask_result, zeros), ones);
+            // There is no unsigned comparison, so flip the sign bits and compare them signed
+            let is_transposition = _mm256_and_si256(_mm256_cmpgt_epi16(
+                _mm256_xor_si256(last_match_letter_index, sign_bit),
+                _mm256_xor_si256(_mm256_sub_epi16(mask_result, ones), sign_bit),
+            ), ones);
+
+            _mm256_storeu_si256(used_ptr, _mm256_or_si256(used, mask_result));
+            _mm256_storeu_si256(used_exact_ptr, _mm256_or_si256(_mm256_loadu_si256(used_exact_ptr), _mm256_and_si256(mask_result, exact_bit)));
+            _mm256_storeu_si256(matches_ptr, _mm256_add_epi16(_mm256_loadu_si256(matches_ptr), is_match));
+            _mm256_storeu_si256(transposition_count_ptr, _mm256_add_epi16(_mm256_loadu_si256(transposition_count_ptr), is_transposition));
+            _mm256_storeu_si256(last_match_letter_index_ptr, _mm256_or_si256(last_match_letter_index, mask_result));
+        }
+    }
+
+    /// Scores 8 candidates at a time. See `score_letter_lanes`.
+    #[target_feature(enable = "sse4.1")]
+    pub(super) unsafe fn score_letter_sse41(scores: &mut CandidateScores, query_mask_by_candidate_len: &[u16; 16], candidate_masks: &[u16], len_indices: &[u8], query_index: usize) {
+        let (low_bytes, high_bytes) = byte_tables(query_mask_by_candidate_len);
+        let low_table = _mm_loadu_si128(low_bytes.as_ptr() as *const __m128i);
+        let high_table = _mm_loadu_si128(high_bytes.as_ptr() as *const __m128i);
+        let low_byte_mask = _mm_set1_epi16(0xff);
+        let exact_bit = _mm_set1_epi16(1u16.wrapping_shl(query_index as u32) as i16);
+        let ones = _mm_set1_epi16(1);
+        let zeros = _mm_setzero_si128();
+        let sign_bit = _mm_set1_epi16(i16::MIN);
+        for i in (0..candidate_masks.len()).step_by(LANES / 2) {
+            let candidate_mask = _mm_loadu_si128(candidate_masks.as_ptr().add(i) as *const __m128i);
+            if _mm_testz_si128(candidate_mask, candidate_mask) == 1 {
+                continue;
+            }
+            // Look up the query mask for each candidate's length, one byte at a time
+            let len_index = _mm_cvtepu8_epi16(_mm_loadl_epi64(len_indices.as_ptr().add(i) as *const __m128i));
+            let low_query_mask = _mm_and_si128(_mm_shuffle_epi8(low_table, len_index), low_byte_mask);
+            let high_query_mask = _mm_slli_epi16(_mm_shuffle_epi8(high_table, len_index), 8);
+            let query_mask = _mm_or_si128(low_query_mask, high_query_mask);
+
+            let used_ptr = scores.used.as_mut_ptr().add(i) as *mut __m128i;
+            let used_exact_ptr = scores.used_exact.as_mut_ptr().add(i) as *mut __m128i;
+            let matches_ptr = scores.matches.as_mut_ptr().add(i) as *mut __m128i;
+            let transposition_count_ptr = scores.transposition_count.as_mut_ptr().add(i) as *mut __m128i;
+            let last_match_letter_index_ptr = scores.last_match_letter_index.as_mut_ptr().add(i) as *mut __m128i;
+            let used = _mm_loadu_si128(used_ptr);
+            let last_match_letter_index = _mm_loadu_si128(last_match_letter_index_ptr);
+
+            let whole_mask_result = _mm_and_si128(query_mask, candidate_mask);
+            let check_used_result = _mm_andnot_si128(used, whole_mask_result);
+            let mask_result = _mm_and_si128(check_used_result, _mm_sub_epi16(zeros, check_used_result));
+            let is_match = _mm_andnot_si128(_mm_cmpeq_epi16(mask_result, zeros), ones);
+            // There is no unsigned comparison, so flip the sign bits and compare them signed
+            let is_transposition = _mm_and_si128(_mm_cmpgt_epi16(
+                _mm_xor_si128(last_match_letter_index, sign_bit),
+                _mm_xor_si128(_mm_sub_epi16(mask_result, ones), sign_bit),
+            ), ones);
+
+            _mm_storeu_si128(used_ptr, _mm_or_si128(used, mask_result));
+            _mm_storeu_si128(used_exact_ptr, _mm_or_si128(_mm_loadu_si128(used_exact_ptr), _mm_and_si128(mask_result, exact_bit)));
+            _mm_storeu_si128(matches_ptr, _mm_add_epi16(_mm_loadu_si128(matches_ptr), is_match));
+            _mm_storeu_si128(transposition_count_ptr, _mm_add_epi16(_mm_loadu_si128(transposition_count_ptr), is_transposition));
+            _mm_storeu_si128(last_match_letter_index_ptr, _mm_or_si128(last_match_letter_index, mask_result));
+        }
+    }
+}
@@ -4,25 +4,94 @@
 //   https://github.com/ipums/pseudo_jaro_winkler
 
 //! Creates fast pseudo jaro winkler scores between two vectors of strings.
+//!
+//! Only the `std` and `parallel` features are on by default. Matching files of names needs `io`,
+//! and the command line program `cli`. With `default-features = false` the library is `no_std`
+//! and only matches names one at a time with `CandidateIndex`.
 
 #![allow(arithmetic_overflow)]
+#![cfg_attr(not(feature = "std"), no_std)]
+extern crate alloc;
+#[cfg(all(test, not(feature = "std")))]
+extern crate std;
+use alloc::collections::BTreeMap;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
 use itertools::Itertools;
+use core::cmp::Reverse;
+#[cfg(feature = "io")]
 use std::collections::HashMap;
-use std::path::PathBuf;
-use std::fs::{File, create_dir_all};
+#[cfg(feature = "io")]
+use std::path::{Path, PathBuf};
+#[cfg(feature = "io")]
+use std::fs::create_dir_all;
+#[cfg(feature = "baselines")]
+use std::fs::File;
+#[cfg(feature = "baselines")]
 use std::io::BufWriter;
-use indicatif::ParallelProgressIterator;
+#[cfg(feature = "io")]
 use rayon::prelude::*;
-use std::io::prelude::*;
-use std::fmt;
+#[cfg(feature = "io")]
+use std::io::{self, prelude::*};
+use core::fmt;
+#[cfg(feature = "std")]
+use std::time::Instant;
+
+mod array;
+mod cancel;
+#[cfg(feature = "io")]
+mod checkpoint;
+#[cfg(feature = "parallel")]
+mod evaluate;
+mod exact;
+mod kernel;
+#[cfg(feature = "io")]
+mod mapped;
+mod progress;
+mod recall;
+#[cfg(feature = "serve")]
+mod serve;
+#[cfg(feature = "io")]
+mod shard;
+mod update;
+use array::Array;
+use kernel::DenseCandidates;
+use update::Updates;
+pub use cancel::CancellationToken;
+#[cfg(feature = "io")]
+pub use cancel::MatchOutcome;
+#[cfg(feature = "io")]
+pub use checkpoint::{Checkpoint, MANIFEST_FILE_NAME};
+#[cfg(feature = "io")]
+use checkpoint::{write_file, Manifest};
+#[cfg(feature = "parallel")]
+pub use evaluate::{evaluate, Evaluation, EvaluateOptions, ScoredPair, ERROR_BUCKETS};
+pub use exact::jaro_winkler;
+pub use kernel::Kernel;
+pub use progress::{NoProgress, ProgressReporter};
+#[cfg(feature = "std")]
+pub use progress::LogProgress;
+#[cfg(feature = "progress")]
+pub use progress::BarProgress;
+pub use recall::{max_deviation, recall_safe_threshold};
+#[cfg(feature = "serve")]
+pub use serve::{MatchServer, MAX_BODY_BYTES};
+#[cfg(feature = "io")]
+pub use shard::{merge_shard_names, merge_shards, pseudo_jaro_winkler_sharded, pseudo_jaro_winkler_sharded_streaming, shard_range};
+#[cfg(feature = "std")]
+pub use update::Compaction;
 
 /// Information on a single letter for a candidate match.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
 struct CandidateLetterInfo {
     /// Index of the candidate within the array of unique names
-    name_index: usize,
+    name_index: u32,
     /// Length of the original candidate
-    len: usize,
+    len: u16,
     /// Bitmask for the letter indicating its position in the candidate name
     mask: u16
 }
@@ -65,10 +134,14 @@ impl CandidateScore {
         CandidateScore { matches: 0, used_exact: 0, used: 0, transposition_count: 0, last_match_letter_index: 0, len_partial: ((1.0 / len as f64) * 1024.0) as u16 }
     }
 
-    /// Calculates the jaro winkler for a candidate score.
+    /// Calculates the jaro winkler for a candidate score, which is 0.0 when nothing matched, the
+    /// same as strsim.
     /// This method should only be used once all the scoring is complete.
     #[inline]
     fn calculate_jaro_winkler(&self, query_partial: u16) -> f32 {
+        if self.matches == 0 {
+            return 0.0;
+        }
         let transpositions = if self.transposition_count > self.matches / 2 { self.transposition_count - 1 } else { self.transposition_count };
         let partial = ((self.matches as u16 * self.len_partial) + (self.matches as u16 * query_partial)) as f32 / 1024.0;
         let jaro = (partial + 1.0 - (transpositions as f32 / self.matches as f32)) / 3.0;
@@ -89,11 +162,12 @@ impl CandidateScore {
 /// # Arguments
 /// 
 /// * `query` - the string to turn into a list of bitmasks
-fn maskify(query: &String) -> Vec<(u8, [u16; 16])> {
+#[inline]
+fn maskify(query: &str) -> Vec<(u8, [u16; 16])> {
     let len = query.len();
     let min_match_dist = if len > 3 { len / 2 - 1 } else { 0 };
     query.replace(" ", "`").chars().enumerate().map( |(i, c)| {
-        let index = c as u8 - '`' as u8;
+        let index = c as u8 - b'`';
         let base_mask = 1 << i;
         let mut masks_by_candidate_len: [u16; 16] = [0; 16];
         for candidate_len in 1..17 {
@@ -102,7 +176,7 @@ fn maskify(query: &String) -> Vec<(u8, [u16; 16])> {
             } else {
                 candidate_len / 2 - 1
             };
-            let mut query_mask = base_mask.clone();
+            let mut query_mask = base_mask;
             for _ in 0..match_distance {
                 query_mask = query_mask << 1 | query_mask;
                 query_mask = query_mask >> 1 | query_mask;
@@ -113,28 +187,50 @@ fn maskify(query: &String) -> Vec<(u8, [u16; 16])> {
     }).collect()
 }
 
+/// Builds the bitmasks of the positions of every letter within a candidate name. '`' (or a space)
+/// corresponds to index 0, 'a' to 1, and so on.
+fn letter_masks(name: &str) -> [u16; 27] {
+    let mut masks = [0; 27];
+    name.bytes().enumerate().for_each(|(matching_index_in_name, c)| {
+        let letter_index = if c == b' ' { 0 } else { c - b'`' };
+        masks[letter_index as usize] += u16::pow(2, matching_index_in_name as u32);
+    });
+    masks
+}
+
+/// Finds the letter index of every letter of a candidate name, as in `letter_masks`, padded out to
+/// 16 letters with `u8::MAX`.
+fn padded_letters(name: &str) -> [u8; 16] {
+    let mut letters = [u8::MAX; 16];
+    letters.iter_mut().zip(name.bytes()).for_each(|(letter, c)| *letter = if c == b' ' { 0 } else { c - b'`' });
+    letters
+}
+
 /// Transforms a vector of names into a lookup table.
-/// The lookup table is represented by a vector of vectors. The outer vector always has 27 elements
-/// in it, each one corresponding to the letters [`-z], where '`' is the 0th item, 'a' is 1st item, 
-/// 'b' is the 2nd item and so on. The inner vector is a list of all the candidates that contain
-/// that letter. 
-fn build_candidate_lookup(names: &Vec<String>) -> Vec<Vec<CandidateLetterInfo>> {
-    let mut letter_lookup: Vec<Vec<CandidateLetterInfo>> = Vec::new();
-    for letter in '`'..'{' {
-        let mut candidate_infos : Vec<CandidateLetterInfo> = Vec::new();
-        for (name_index, name) in names.iter().enumerate() {
-            let mut mask : u16 = 0;
-            name.replace(" ", "`").chars().positions(|c| c == letter).for_each(|matching_index_in_name| {
-                mask += u16::pow(2, matching_index_in_name as u32);
-            });
-            if mask != 0 {
-                let info = CandidateLetterInfo { name_index, len: name.len(), mask };
-                candidate_infos.push(info);
-            }
-        }
-        letter_lookup.push(candidate_infos);
+/// The lookup table is a list of all the candidates that contain each letter, ordered by the
+/// length of the candidate, with the lists for the letters [`-z] one after another. '`' is the 0th
+/// list, 'a' is the 1st list, 'b' is the 2nd list and so on. Along with it are the offsets of the
+/// candidates of each length within each letter's list: the candidates of length `len` containing
+/// the letter `letter` are at `len_offsets[letter][len - 1]..len_offsets[letter][len]`.
+fn build_candidate_lookup(names: &[String]) -> (Vec<CandidateLetterInfo>, Vec<[u64; 17]>) {
+    assert!(names.len() <= u32::MAX as usize, "there can be at most {} unique candidate names", u32::MAX);
+    let mut letter_lookup: Vec<Vec<CandidateLetterInfo>> = vec![Vec::new(); 27];
+    for (name_index, name) in names.iter().enumerate() {
+        letter_masks(name).iter().enumerate().filter(|(_, &mask)| mask != 0).for_each(|(letter_index, &mask)| {
+            letter_lookup[letter_index].push(CandidateLetterInfo { name_index: name_index as u32, len: name.len() as u16, mask });
+        });
     }
-    letter_lookup
+    let mut candidate_lookup = Vec::with_capacity(letter_lookup.iter().map(Vec::len).sum());
+    let len_offsets = letter_lookup.iter_mut().map(|candidate_infos| {
+        candidate_infos.sort_by_key(|c_info| c_info.len);
+        let mut offsets = [0; 17];
+        offsets.iter_mut().enumerate().for_each(|(len, offset)| {
+            *offset = (candidate_lookup.len() + candidate_infos.partition_point(|c_info| c_info.len as usize <= len)) as u64;
+        });
+        candidate_lookup.extend_from_slice(candidate_infos);
+        offsets
+    }).collect();
+    (candidate_lookup, len_offsets)
 }
 
 /// Updates the score for a single candidate given a query mask, candidate mask, and query index.
@@ -143,26 +239,590 @@ fn build_candidate_lookup(names: &Vec<String>) -> Vec<Vec<CandidateLetterInfo>>
 ///
 /// * `candidate_score`: the score card to update for a given candidate
 /// * `query_mask`: the mask of the query which represents where possible matches for the letter
-///    are in the query. This mask must take into account the lengths of both the strings and have
-///    '1's for all possible matches.
+///   are in the query. This mask must take into account the lengths of both the strings and have
+///   '1's for all possible matches.
 /// * `candidate_mask`: the mask of the candidate which represents the location of any occurences
-///    of the charcter
+///   of the charcter
 /// * `query_index`: The index of the letter that this is for. '`' corresponds to 0, 'a' to 1, and
-///    so on.
+///   so on.
 #[inline]
 fn score_letter(candidate_score: &mut CandidateScore, query_mask: u16, candidate_mask: u16, query_index: usize) {
     let whole_mask_result = query_mask & candidate_mask; // Get raw matches
     let check_used_result = (whole_mask_result | candidate_score.used) ^ candidate_score.used; // Make sure we haven't used that match before
-    let last_match_letter_index = (1 << check_used_result.trailing_zeros()) & check_used_result; // Find the first match found
+    let last_match_letter_index = 1u16.wrapping_shl(check_used_result.trailing_zeros()) & check_used_result; // Find the first match found
     let mask_result = check_used_result & last_match_letter_index; // Take the first match found
-    let is_match_mask = !(((mask_result >> mask_result.trailing_zeros()) & 1) - 1); // All 1s if there is a result, else all 0s
+    let is_match_mask = !((mask_result.wrapping_shr(mask_result.trailing_zeros()) & 1).wrapping_sub(1)); // All 1s if there is a result, else all 0s
     candidate_score.used |= mask_result;
     candidate_score.used_exact |= mask_result & (1 << query_index);
     candidate_score.matches += (is_match_mask & 1) as u8;
-    candidate_score.transposition_count +=  (mask_result - 1 < candidate_score.last_match_letter_index) as u8;
+    candidate_score.transposition_count +=  (mask_result.wrapping_sub(1) < candidate_score.last_match_letter_index) as u8;
     candidate_score.last_match_letter_index |= mask_result;
 }
 
+/// Scores a single candidate, given its letter indices padded out to 16 letters and its length.
+#[inline(always)]
+fn score_padded_letters(letters: &[u8; 16], len: u8, query_masks_lookup: &[(u8, [u16; 16])], query_partial: u16) -> f32 {
+    let mut score = CandidateScore::new(len);
+    for (query_index, (letter_index, query_mask_by_candidate_len)) in query_masks_lookup.iter().enumerate() {
+        let query_mask = query_mask_by_candidate_len[len as usize - 1];
+        let candidate_mask = letters.iter().enumerate().fold(0, |mask, (i, letter)| mask | ((letter == letter_index) as u16) << i);
+        score_letter(&mut score, query_mask, candidate_mask, query_index);
+    }
+    score.calculate_jaro_winkler(query_partial)
+}
+
+/// Describes the names that `is_valid_name` accepts, for the errors about invalid names.
+pub const VALID_NAME_DESCRIPTION: &str = "names must be 1 to 16 lowercase letters, spaces or '`'";
+
+/// Whether a name can be scored: it must have 1 to 16 letters, each either a lowercase letter, a
+/// space or a '`'. Other names never match: they are left out of indexes, and queries with them
+/// have no matches.
+pub fn is_valid_name(name: &str) -> bool {
+    (1..=16).contains(&name.len()) && name.bytes().all(|c| c.is_ascii_lowercase() || c == b' ' || c == b'`')
+}
+
+/// Reads names, one per line, checking that each one is valid. After the names before it, an
+/// invalid name is returned as an error with its line number. See `is_valid_name`.
+#[cfg(feature = "io")]
+pub fn read_names<R: BufRead>(reader: R) -> impl Iterator<Item = io::Result<String>> {
+    read_names_checked(reader, false)
+}
+
+/// Reads names, one per line, the same as `read_names` except that blank lines are read as empty
+/// names, to be matched according to `MatchOptions::empty_names`.
+#[cfg(feature = "io")]
+pub fn read_names_or_empty<R: BufRead>(reader: R) -> impl Iterator<Item = io::Result<String>> {
+    read_names_checked(reader, true)
+}
+
+#[cfg(feature = "io")]
+fn read_names_checked<R: BufRead>(reader: R, allow_empty: bool) -> impl Iterator<Item = io::Result<String>> {
+    reader.lines().enumerate().map(move |(i, name)| {
+        let name = name?;
+        if is_valid_name(&name) || (allow_empty && name.is_empty()) {
+            Ok(name)
+        } else {
+            Err(io::Error::new(io::ErrorKind::InvalidData, format!("line {}: {:?} isn't a valid name, {}", i + 1, name, VALID_NAME_DESCRIPTION)))
+        }
+    })
+}
+
+/// The order in which the letters of a query are scored against the candidates.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScoringStrategy {
+    /// Scores every letter of the query, in order, against every candidate containing it.
+    AllLetters,
+    /// Looks up the query's rarest letters first and only scores the candidates which contain at
+    /// least one of them. Candidates missing all of those letters can't have enough matches to
+    /// reach the threshold, so they are skipped without being scored. This is a form of the prefix
+    /// filtering used in set similarity joins. The scores found are identical to `AllLetters`.
+    RareLettersFirst,
+}
+
+/// What to do with empty names, which have no letters to score. Other invalid names are never
+/// matched. See `is_valid_name`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum EmptyNames {
+    /// Never matches empty names: empty queries have no matches, and empty candidates are never
+    /// matched.
+    #[default]
+    Skip,
+    /// Fails with an `EmptyNameError` when matching an empty query, or when matching any query
+    /// against candidates which include an empty name. See `CandidateIndex::matches`. The
+    /// functions matching files of names panic with the error, as they do when writing fails.
+    Error,
+    /// Scores empty names the same as `jaro_winkler` and strsim: 1.0 for two empty names, and 0.0
+    /// for an empty name and any other name. Pairs scoring 0.0 are only kept with a
+    /// `min_jaro_winkler` of 0.0.
+    Score,
+}
+
+/// The error returned when matching an empty name with `EmptyNames::Error`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EmptyNameError {
+    /// The query was empty.
+    Query,
+    /// The candidates include an empty name.
+    Candidate,
+}
+
+impl fmt::Display for EmptyNameError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EmptyNameError::Query => write!(f, "matching an empty name, see MatchOptions::empty_names"),
+            EmptyNameError::Candidate => write!(f, "the candidates include an empty name, see MatchOptions::empty_names"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for EmptyNameError {}
+
+/// The order in which the matches of each query are written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MatchOrder {
+    /// The order the candidates happen to be scored in, which depends on how names_b is
+    /// deduplicated, or from best to worst when keeping the `top_k` best matches. This is the
+    /// fastest.
+    #[default]
+    Unsorted,
+    /// From best to worst score as written, then by index within names_b. See `keep_top_k`.
+    ByScore,
+    /// By index within names_b.
+    ByCandidate,
+}
+
+/// Options for comparing two vectors of strings.
+#[derive(Debug, Clone)]
+pub struct MatchOptions {
+    /// The minimum jaro winkler threshold for writing an output match. Use 0.0 to write all
+    /// matches.
+    pub min_jaro_winkler: f32,
+    /// The order in which to score the letters of each query.
+    pub strategy: ScoringStrategy,
+    /// The instruction set used to score every letter with `ScoringStrategy::AllLetters`. It must
+    /// be supported by the CPU.
+    pub kernel: Kernel,
+    /// Only write the best `top_k` matches for each name, from best to worst. See `keep_top_k`.
+    pub top_k: Option<usize>,
+    /// The order in which to write the matches of each name. See `sort_matches`.
+    pub order: MatchOrder,
+    /// If given, candidates whose pseudo score is within this margin of `min_jaro_winkler`, above
+    /// or below, are rescored with an exact jaro winkler and only kept if that reaches the
+    /// threshold, with the exact score. This makes the matches near the threshold exact at nearly
+    /// the speed of the pseudo scores. The exact scores are strsim 0.10's, see `jaro_winkler`.
+    pub verify_margin: Option<f32>,
+    /// Whether to never miss a candidate whose exact jaro winkler reaches `min_jaro_winkler`.
+    /// Candidates are scored against the lowest pseudo score such a candidate could have, and
+    /// every candidate found is rescored with an exact jaro winkler and only kept if that reaches
+    /// the threshold. This is slower, more so for lower thresholds. The exact scores are strsim
+    /// 0.10's, see `jaro_winkler` and `recall_safe_threshold`.
+    pub recall_safe: bool,
+    /// What to do with empty query and candidate names. See `EmptyNames`.
+    pub empty_names: EmptyNames,
+    /// Reports the progress of matching files of names, such as `BarProgress` or `LogProgress`.
+    pub progress: Arc<dyn ProgressReporter>,
+    /// Stops matching files of names once cancelled. See `MatchOutcome`.
+    pub cancellation: CancellationToken,
+    /// Stops matching files of names once this time has passed. See `MatchOutcome`.
+    #[cfg(feature = "std")]
+    pub deadline: Option<Instant>,
+    /// Whether to record the names written in a manifest within the output directory, so that a
+    /// stopped run can be resumed. See `Checkpoint`.
+    #[cfg(feature = "io")]
+    pub checkpoint: Checkpoint,
+}
+
+impl MatchOptions {
+    /// Creates options with the given threshold which score every letter using the fastest kernel
+    /// the CPU supports, and write every match without reporting progress, stopping early or
+    /// checkpointing.
+    pub fn new(min_jaro_winkler: f32) -> MatchOptions {
+        MatchOptions {
+            min_jaro_winkler,
+            strategy: ScoringStrategy::AllLetters,
+            kernel: Kernel::detect(),
+            top_k: None,
+            order: MatchOrder::Unsorted,
+            verify_margin: None,
+            recall_safe: false,
+            empty_names: EmptyNames::Skip,
+            progress: Arc::new(NoProgress),
+            cancellation: CancellationToken::new(),
+            #[cfg(feature = "std")]
+            deadline: None,
+            #[cfg(feature = "io")]
+            checkpoint: Checkpoint::Off,
+        }
+    }
+
+    /// Whether the cancellation token was cancelled or the deadline has passed, in which case no
+    /// more names should be matched.
+    pub fn is_stopped(&self) -> bool {
+        #[cfg(feature = "std")]
+        if self.deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            return true;
+        }
+        self.cancellation.is_cancelled()
+    }
+}
+
+/// The score to keep a candidate with, given its pseudo score, or `None` if it doesn't reach the
+/// threshold. Pseudo scores within the options' `verify_margin` of the threshold, or every pseudo
+/// score when `recall_safe`, are replaced with the exact jaro winkler.
+#[inline]
+fn verify(query_name: &str, name: &str, jw: f32, options: &MatchOptions) -> Option<f32> {
+    let jw = match options.verify_margin {
+        _ if options.recall_safe => jaro_winkler(query_name, name) as f32,
+        Some(verify_margin) if jw < options.min_jaro_winkler + verify_margin => jaro_winkler(query_name, name) as f32,
+        _ => jw,
+    };
+    if jw >= options.min_jaro_winkler { Some(jw) } else { None }
+}
+
+/// Finds the fewest matching letters that a candidate of length `candidate_len` needs in order for
+/// its pseudo jaro winkler with a query of length `query_len` to possibly reach `min_jaro_winkler`.
+/// Returns `None` if the candidate could never reach it.
+///
+/// The best case for a given number of matches is no transpositions and a common prefix as long as
+/// possible. The fixed point partials used by `CandidateScore` round down, so they never score
+/// higher than this.
+fn min_matches_needed(query_len: usize, candidate_len: usize, min_jaro_winkler: f32) -> Option<usize> {
+    (0..=query_len.min(candidate_len)).find(|&matches| {
+        let best_jaro_winkler = if matches == 0 { 0.0 } else {
+            let jaro = (matches as f64 / candidate_len as f64 + matches as f64 / query_len as f64 + 1.0) / 3.0;
+            jaro + 0.1 * matches.min(4) as f64 * (1.0 - jaro)
+        };
+        best_jaro_winkler >= min_jaro_winkler as f64 - 0.0001
+    })
+}
+
+/// Finds the fewest matching letters needed for each candidate length, as in `min_matches_needed`,
+/// indexed by the length minus one. Lengths which could never reach the threshold need
+/// `u16::MAX` matches. This is used to skip calculating the jaro winklers of candidates which
+/// don't have enough matches.
+fn min_matches_by_candidate_len(query_len: usize, min_jaro_winkler: f32) -> [u16; 16] {
+    let mut min_matches = [u16::MAX; 16];
+    min_matches.iter_mut().enumerate().for_each(|(len_index, min_matches)| {
+        if let Some(needed) = min_matches_needed(query_len, len_index + 1, min_jaro_winkler) {
+            *min_matches = needed as u16;
+        }
+    });
+    min_matches
+}
+
+/// Keeps only the best `top_k` matches, ordered from best to worst.
+/// Matches are ranked by their score as written to the output files, and ties are broken by the
+/// index of the match within names_b. This way the same matches are kept no matter how names_b is
+/// sharded, even when merging shards from their output files.
+///
+/// # Arguments
+///
+/// * `matches`: The index within names_b and the score of each match.
+/// * `top_k`: The number of matches to keep.
+pub fn keep_top_k(matches: &mut Vec<(usize, f32)>, top_k: usize) {
+    matches.sort_by_cached_key(|&(b_id, jw)| rank(b_id, jw));
+    matches.truncate(top_k);
+}
+
+/// Sorts matches into the given order, so that the output doesn't depend on how names_b is
+/// deduplicated or sharded.
+///
+/// # Arguments
+///
+/// * `matches`: The index within names_b and the score of each match.
+/// * `order`: The order to sort them into. `MatchOrder::Unsorted` leaves them as they are.
+pub fn sort_matches(matches: &mut [(usize, f32)], order: MatchOrder) {
+    match order {
+        MatchOrder::Unsorted => {},
+        MatchOrder::ByScore => matches.sort_by_cached_key(|&(b_id, jw)| rank(b_id, jw)),
+        MatchOrder::ByCandidate => matches.sort_unstable_by_key(|&(b_id, _)| b_id),
+    }
+}
+
+/// The key that matches are sorted by from best to worst. See `keep_top_k`.
+fn rank(b_id: usize, jw: f32) -> (Reverse<u32>, usize) {
+    (Reverse(written_score(jw)), b_id)
+}
+
+/// The score as it is written to the output files, in hundredths.
+fn written_score(jw: f32) -> u32 {
+    format!("{:.2}", jw).replace('.', "").parse().unwrap()
+}
+
+/// The deduplicated candidate names (names_b) along with the lookup tables needed to score queries
+/// against them. Building an index once lets it be reused to match any number of names.
+///
+/// The index is made up of flat arrays, so it can be saved to a file with `CandidateIndex::save`
+/// and memory-mapped with `CandidateIndex::open` rather than being rebuilt. Names can be inserted
+/// and removed after it is built, and are folded into the arrays by compacting it. See
+/// `CandidateIndex::insert`.
+#[derive(Clone)]
+pub struct CandidateIndex {
+    /// The unique candidate names, sorted, one after another.
+    name_bytes: Array<u8>,
+    /// Where each unique name starts within `name_bytes`, followed by the end of the last one.
+    name_offsets: Array<u64>,
+    /// The indices in the original list of candidates of every unique name, one unique name after
+    /// another. The unique names are sorted.
+    b_ids: Array<u64>,
+    /// Where the indices of each unique name start within `b_ids`, followed by the end of the
+    /// last one.
+    b_id_offsets: Array<u64>,
+    /// The candidates containing each letter. See `build_candidate_lookup`.
+    candidate_lookup: Array<CandidateLetterInfo>,
+    /// Where the candidates of each length start within `candidate_lookup`. See
+    /// `build_candidate_lookup`.
+    len_offsets: Array<[u64; 17]>,
+    /// The letter indices of each unique name, padded out to 16 letters.
+    padded_letters: Array<[u8; 16]>,
+    /// The length of each unique name.
+    name_lens: Array<u8>,
+    /// The unique names laid out for the SIMD kernels.
+    dense_candidates: DenseCandidates,
+    /// The indices in the original list of candidates of the empty names, which aren't among the
+    /// unique names since they have no letters to score. See `EmptyNames`.
+    empty_b_ids: Array<u64>,
+    /// The names inserted and removed since the index was built or last compacted.
+    updates: Updates,
+}
+
+impl CandidateIndex {
+    /// Builds an index of all the candidate names. Invalid names are left out, so they never match,
+    /// apart from empty names which are matched according to `MatchOptions::empty_names`. See
+    /// `is_valid_name`.
+    pub fn new(names: &[String]) -> CandidateIndex {
+        CandidateIndex::new_shard(names, 0)
+    }
+
+    /// Builds an index of a shard of the candidate names, where `names` is the slice of all the
+    /// candidate names starting at `first_id`. Matches are written with their index within all the
+    /// candidate names rather than within the shard.
+    pub fn new_shard(names: &[String], first_id: usize) -> CandidateIndex {
+        let lookup_by_name = names.iter().enumerate().filter(|(_, name)| is_valid_name(name) || name.is_empty()).fold(BTreeMap::new(), |mut lookup, (i, name)|  { 
+            let entry = lookup.entry(name.as_str()).or_insert_with(Vec::new);
+            entry.push(first_id + i);
+            lookup
+        });
+        CandidateIndex::from_lookup_by_name(lookup_by_name)
+    }
+
+    /// Builds an index from the indices in the original list of candidates of each unique name.
+    fn from_lookup_by_name(mut lookup_by_name: BTreeMap<&str, Vec<usize>>) -> CandidateIndex {
+        let empty_b_ids = lookup_by_name.remove("").unwrap_or_default().into_iter().sorted().map(|b_id| b_id as u64).collect::<Vec<_>>();
+        let unique_names = lookup_by_name.keys().map(|name| name.to_string()).collect::<Vec<String>>();
+        let mut name_bytes = Vec::new();
+        let mut name_offsets = vec![0];
+        let mut b_ids = Vec::new();
+        let mut b_id_offsets = vec![0];
+        unique_names.iter().for_each(|name| {
+            name_bytes.extend_from_slice(name.as_bytes());
+            name_offsets.push(name_bytes.len() as u64);
+            b_ids.extend(lookup_by_name[name.as_str()].iter().sorted().map(|&b_id| b_id as u64));
+            b_id_offsets.push(b_ids.len() as u64);
+        });
+        let (candidate_lookup, len_offsets) = build_candidate_lookup(&unique_names);
+        let padded_letters = unique_names.iter().map(|name| padded_letters(name)).collect::<Vec<_>>();
+        let name_lens = unique_names.iter().map(|name| name.len() as u8).collect::<Vec<_>>();
+        let dense_candidates = DenseCandidates::new(&unique_names);
+        CandidateIndex {
+            name_bytes: name_bytes.into(),
+            name_offsets: name_offsets.into(),
+            b_ids: b_ids.into(),
+            b_id_offsets: b_id_offsets.into(),
+            candidate_lookup: candidate_lookup.into(),
+            len_offsets: len_offsets.into(),
+            padded_letters: padded_letters.into(),
+            name_lens: name_lens.into(),
+            dense_candidates,
+            empty_b_ids: empty_b_ids.into(),
+            updates: Updates::default(),
+        }
+    }
+
+    /// The unique name at an index.
+    fn name(&self, name_index: usize) -> &str {
+        let bytes = &self.name_bytes[self.name_offsets[name_index] as usize..self.name_offsets[name_index + 1] as usize];
+        core::str::from_utf8(bytes).expect("Invalid candidate index: a name isn't UTF-8")
+    }
+
+    /// The indices in the original list of candidates of a unique name.
+    fn b_ids(&self, name_index: usize) -> &[u64] {
+        &self.b_ids[self.b_id_offsets[name_index] as usize..self.b_id_offsets[name_index + 1] as usize]
+    }
+
+    /// The candidates containing a letter whose lengths are in a range. See
+    /// `build_candidate_lookup`.
+    #[inline(always)]
+    fn candidates_with_letter(&self, letter_index: usize, min_len: usize, max_len: usize) -> &[CandidateLetterInfo] {
+        let offsets = &self.len_offsets[letter_index];
+        &self.candidate_lookup[offsets[min_len - 1] as usize..offsets[max_len] as usize]
+    }
+
+    /// An empty score card for each unique name.
+    #[inline(always)]
+    fn base_candidate_scores(&self) -> Vec<CandidateScore> {
+        let base_scores_by_len = (1..17).map(|len| CandidateScore::new(len as u8)).collect::<Vec<_>>();
+        self.name_lens.iter().map(|&len| base_scores_by_len[len as usize - 1].clone()).collect()
+    }
+
+    /// Scores a query against the candidates, returning the index in the original list of
+    /// candidates and the score of every match, the same as the matches written to the output
+    /// files. The matches are in the order given by the options, and are otherwise ordered from
+    /// best to worst when keeping the `top_k` best ones.
+    ///
+    /// # Arguments
+    ///
+    /// * `query_name`: The name to match. Invalid names have no matches, see `is_valid_name`.
+    /// * `options`: How to score the matches. See `MatchOptions`.
+    ///
+    /// # Errors
+    ///
+    /// Only fails with `EmptyNames::Error`, when the query or one of the candidates is empty.
+    pub fn matches(&self, query_name: &str, options: &MatchOptions) -> Result<Vec<(usize, f32)>, EmptyNameError> {
+        let mut matches = self.named_matches(query_name, options)?.into_iter().map(|(b_id, _, jw)| (b_id, jw)).collect();
+        if let Some(top_k) = options.top_k {
+            keep_top_k(&mut matches, top_k);
+        }
+        sort_matches(&mut matches, options.order);
+        Ok(matches)
+    }
+
+    /// Scores a query against the candidates, returning the index in the original list of
+    /// candidates, the name and the score of every candidate at or above `min_jaro_winkler`,
+    /// including those inserted since the index was built. The candidates near the threshold are
+    /// verified when there is a `verify_margin`, and every candidate is verified when `recall_safe`.
+    fn named_matches(&self, query_name: &str, options: &MatchOptions) -> Result<Vec<(usize, &str, f32)>, EmptyNameError> {
+        if options.empty_names == EmptyNames::Error {
+            if query_name.is_empty() {
+                return Err(EmptyNameError::Query);
+            } else if self.empty_b_ids().next().is_some() {
+                return Err(EmptyNameError::Candidate);
+            }
+        }
+        if query_name.is_empty() {
+            return Ok(if options.empty_names == EmptyNames::Score { self.empty_name_matches(query_name, options) } else { Vec::new() });
+        }
+        if !is_valid_name(query_name) {
+            return Ok(Vec::new());
+        }
+        let mut min_pseudo_score = match options.verify_margin {
+            Some(verify_margin) => (options.min_jaro_winkler - verify_margin).max(0.0),
+            None => options.min_jaro_winkler,
+        };
+        if options.recall_safe {
+            min_pseudo_score = min_pseudo_score.min(recall_safe_threshold(query_name.len(), options.min_jaro_winkler));
+        }
+        let matches = self.score(query_name, min_pseudo_score, options.strategy, options.kernel);
+        let mut b_matches = matches.into_iter().filter_map(|(name_index, jw)| {
+            let name = self.name(name_index);
+            Some((name_index, name, verify(query_name, name, jw, options)?))
+        }).flat_map(|(name_index, name, jw)| {
+            self.b_ids(name_index).iter().map(move |&b_id| (b_id as usize, name, jw))
+        }).filter(|(b_id, _, _)| !self.updates.is_removed(*b_id)).collect::<Vec<_>>();
+        b_matches.extend(self.updates.score(query_name, min_pseudo_score).into_iter().filter_map(|(b_id, name, jw)| {
+            Some((b_id, name, verify(query_name, name, jw, options)?))
+        }));
+        if options.empty_names == EmptyNames::Score {
+            b_matches.extend(self.empty_name_matches(query_name, options));
+        }
+        Ok(b_matches)
+    }
+
+    /// The indices of the empty candidates, including those inserted since the index was built.
+    fn empty_b_ids(&self) -> impl Iterator<Item = usize> + '_ {
+        let b_ids = self.empty_b_ids.iter().map(|&b_id| b_id as usize).filter(move |&b_id| !self.updates.is_removed(b_id));
+        b_ids.chain(self.updates.inserted_b_ids("").iter().copied())
+    }
+
+    /// Scores a query against the empty candidates, and an empty query against the other
+    /// candidates too, for `EmptyNames::Score`.
+    fn empty_name_matches(&self, query_name: &str, options: &MatchOptions) -> Vec<(usize, &str, f32)> {
+        let empty_jw = if query_name.is_empty() { 1.0 } else { 0.0 };
+        let mut matches = Vec::new();
+        if empty_jw >= options.min_jaro_winkler {
+            matches.extend(self.empty_b_ids().map(|b_id| (b_id, "", empty_jw)));
+        }
+        if query_name.is_empty() && 0.0 >= options.min_jaro_winkler {
+            matches.extend((0..self.name_lens.len()).flat_map(|name_index| {
+                let name = self.name(name_index);
+                self.b_ids(name_index).iter().map(move |&b_id| (b_id as usize, name, 0.0))
+            }).filter(|(b_id, _, _)| !self.updates.is_removed(*b_id)));
+            matches.extend(self.updates.inserted_names().filter(|(_, name)| !name.is_empty()).map(|(b_id, name)| (b_id, name, 0.0)));
+        }
+        matches
+    }
+
+    /// Scores a query against the unique names in the arrays, returning the index of the unique
+    /// name and the score of every candidate at or above `min_jaro_winkler`.
+    ///
+    /// The scoring is compiled twice, once for any CPU and once for CPUs supporting the x86-64-v3
+    /// instructions (AVX2, BMI and friends), and the version used is picked when it is called.
+    /// This keeps the binary portable without giving up the speed of newer CPUs.
+    fn score(&self, query_name: &str, min_jaro_winkler: f32, strategy: ScoringStrategy, kernel: Kernel) -> Vec<(usize, f32)> {
+        #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+        if kernel::has_x86_64_v3() {
+            return unsafe { self.score_x86_64_v3(query_name, min_jaro_winkler, strategy, kernel) };
+        }
+        self.score_any_cpu(query_name, min_jaro_winkler, strategy, kernel)
+    }
+
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    #[target_feature(enable = "avx2,bmi1,bmi2,fma,lzcnt,popcnt")]
+    unsafe fn score_x86_64_v3(&self, query_name: &str, min_jaro_winkler: f32, strategy: ScoringStrategy, kernel: Kernel) -> Vec<(usize, f32)> {
+        self.score_any_cpu(query_name, min_jaro_winkler, strategy, kernel)
+    }
+
+    #[inline(always)]
+    fn score_any_cpu(&self, query_name: &str, min_jaro_winkler: f32, strategy: ScoringStrategy, kernel: Kernel) -> Vec<(usize, f32)> {
+        let query_masks_lookup = maskify(query_name);
+        let query_partial = ((1.0 / query_name.len() as f32) * 1024.0) as u16;
+        match strategy {
+            ScoringStrategy::AllLetters if kernel != Kernel::Scalar => {
+                assert!(kernel.is_available(), "The {:?} kernel isn't supported by this CPU", kernel);
+                unsafe { self.dense_candidates.score(&query_masks_lookup, query_partial, min_jaro_winkler, kernel) }
+            },
+            ScoringStrategy::AllLetters => self.score_all_letters(&query_masks_lookup, query_partial, min_jaro_winkler),
+            ScoringStrategy::RareLettersFirst => self.score_rare_letters_first(&query_masks_lookup, query_partial, min_jaro_winkler),
+        }
+    }
+
+    #[inline(always)]
+    fn score_all_letters(&self, query_masks_lookup: &[(u8, [u16; 16])], query_partial: u16, min_jaro_winkler: f32) -> Vec<(usize, f32)> {
+        let mut candidate_scores = self.base_candidate_scores();
+        for (query_index, (letter_index, query_mask_by_candidate_len)) in query_masks_lookup.iter().enumerate() {
+            for c_info in self.candidates_with_letter(*letter_index as usize, 1, 16).iter() {
+                let candidate_score = &mut candidate_scores[c_info.name_index as usize];
+                let query_mask = query_mask_by_candidate_len[c_info.len as usize - 1];
+                score_letter(candidate_score, query_mask, c_info.mask, query_index);
+            }
+        }
+        let min_matches = min_matches_by_candidate_len(query_masks_lookup.len(), min_jaro_winkler);
+        candidate_scores.into_iter().enumerate().filter(|(score_i, score)| {
+            score.matches as u16 >= min_matches[self.name_lens[*score_i] as usize - 1]
+        }).flat_map(|(score_i, score)| {
+            let jw = score.calculate_jaro_winkler(query_partial);
+            if jw >= min_jaro_winkler {
+                Some((score_i, jw))
+            } else { None}
+        }).collect()
+    }
+
+    /// Scores only the candidates sharing at least one of the query's rarest letters.
+    /// A candidate needing `min_matches` matches which has none of the `query_len - min_matches + 1`
+    /// rarest letters within matching distance can match at most `min_matches - 1` letters, so it
+    /// can't reach the threshold. The number of matches needed depends on the candidate's length,
+    /// so each length is looked up separately using how rare the letters are among candidates of
+    /// that length.
+    #[inline(always)]
+    fn score_rare_letters_first(&self, query_masks_lookup: &[(u8, [u16; 16])], query_partial: u16, min_jaro_winkler: f32) -> Vec<(usize, f32)> {
+        let query_len = query_masks_lookup.len();
+        let mut is_candidate = vec![false; self.name_lens.len()];
+        for candidate_len in 1..17 {
+            let prefix_len = match min_matches_needed(query_len, candidate_len, min_jaro_winkler) {
+                Some(0) => return self.score_all_letters(query_masks_lookup, query_partial, min_jaro_winkler),
+                Some(min_matches) => query_len - min_matches + 1,
+                None => continue,
+            };
+            let candidates_with_letter = |query_index: usize| {
+                self.candidates_with_letter(query_masks_lookup[query_index].0 as usize, candidate_len, candidate_len)
+            };
+            for query_index in (0..query_len).sorted_by_key(|&query_index| candidates_with_letter(query_index).len()).take(prefix_len) {
+                let query_mask = query_masks_lookup[query_index].1[candidate_len - 1];
+                for c_info in candidates_with_letter(query_index).iter() {
+                    is_candidate[c_info.name_index as usize] |= query_mask & c_info.mask != 0;
+                }
+            }
+        }
+        is_candidate.into_iter().positions(|is_candidate| is_candidate).flat_map(|name_index| {
+            let jw = score_padded_letters(&self.padded_letters[name_index], self.name_lens[name_index], query_masks_lookup, query_partial);
+            if jw >= min_jaro_winkler {
+                Some((name_index, jw))
+            } else { None}
+        }).collect()
+    }
+}
+
 /// Compares two vectors of strings using the pseudo jaro winkler algorithm. It calculates the
 /// matches in parallel and will write all matches to the output directory with one file per record
 /// in names_a and all of its associated matches in names_b.
@@ -173,82 +833,147 @@ fn score_letter(candidate_score: &mut CandidateScore, query_mask: u16, candidate
 /// * `names_b`: List of names in the second dataset.
 /// * `output_dir`: The location of the output directory to write matches to.
 /// * `min_jaro_winkler`: The minimum jaro winkler threshold for writing an output match. Use 0.0
-///    to write all matches.
+///   to write all matches, including the candidates which share no letters with a name and score
+///   0.0.
+#[cfg(feature = "io")]
 #[inline]
-pub fn pseudo_jaro_winkler(names_a: &Vec<String>, names_b: &Vec<String>, mut output_dir: PathBuf, min_jaro_winkler: f32) {
-    let lookup_a_by_name = names_a.iter().enumerate().fold(HashMap::new(), |mut lookup, (i, name)|  { 
-        let entry = lookup.entry(name).or_insert(Vec::new());
-        entry.push(i);
+pub fn pseudo_jaro_winkler(names_a: &[String], names_b: &[String], output_dir: PathBuf, min_jaro_winkler: f32) {
+    pseudo_jaro_winkler_with_options(names_a, names_b, output_dir, &MatchOptions::new(min_jaro_winkler));
+}
+
+/// Compares two vectors of strings using the pseudo jaro winkler algorithm, the same as
+/// `pseudo_jaro_winkler` but with the given options.
+///
+/// # Arguments
+///
+/// * `names_a`: List of names in the first dataset.
+/// * `names_b`: List of names in the second dataset.
+/// * `output_dir`: The location of the output directory to write matches to.
+/// * `options`: How to score and write the matches. See `MatchOptions`.
+#[cfg(feature = "io")]
+pub fn pseudo_jaro_winkler_with_options(names_a: &[String], names_b: &[String], output_dir: PathBuf, options: &MatchOptions) -> MatchOutcome {
+    let candidate_index = CandidateIndex::new(names_b);
+    pseudo_jaro_winkler_with_index(names_a.iter().cloned(), &candidate_index, output_dir, options, names_a.len().max(1))
+}
+
+/// Compares a stream of names to a vector of strings using the pseudo jaro winkler algorithm. The
+/// names in the stream are read `chunk_size` at a time, and each chunk is matched in parallel and
+/// written out before reading the next one, so only the candidates and a single chunk are ever
+/// held in memory. This writes the same output as `pseudo_jaro_winkler_with_options`, with the
+/// output files numbered by each name's position in the stream.
+///
+/// # Arguments
+///
+/// * `names_a`: Names in the first dataset, which may be larger than memory.
+/// * `names_b`: List of names in the second dataset.
+/// * `output_dir`: The location of the output directory to write matches to.
+/// * `options`: How to score and write the matches. See `MatchOptions`.
+/// * `chunk_size`: The number of names from `names_a` to match at a time.
+#[cfg(feature = "io")]
+pub fn pseudo_jaro_winkler_streaming<I>(names_a: I, names_b: &[String], output_dir: PathBuf, options: &MatchOptions, chunk_size: usize) -> MatchOutcome
+where I: IntoIterator<Item = String> {
+    let candidate_index = CandidateIndex::new(names_b);
+    pseudo_jaro_winkler_with_index(names_a, &candidate_index, output_dir, options, chunk_size)
+}
+
+/// Compares a stream of names to an already built index of candidates, a chunk at a time, the same
+/// as `pseudo_jaro_winkler_streaming`. Once stopped, no more names are read from the stream. When
+/// checkpointing, the manifest is written after every chunk.
+///
+/// # Arguments
+///
+/// * `names_a`: Names in the first dataset, which may be larger than memory.
+/// * `candidate_index`: The index of the names in the second dataset, or a shard of them.
+/// * `output_dir`: The location of the output directory to write matches to.
+/// * `options`: How to score and write the matches. See `MatchOptions`.
+/// * `chunk_size`: The number of names from `names_a` to match at a time.
+#[cfg(feature = "io")]
+pub fn pseudo_jaro_winkler_with_index<I>(names_a: I, candidate_index: &CandidateIndex, mut output_dir: PathBuf, options: &MatchOptions, chunk_size: usize) -> MatchOutcome
+where I: IntoIterator<Item = String> {
+    assert!(chunk_size > 0, "chunk_size must be greater than 0");
+    create_dir_all(&mut output_dir).unwrap();
+    let names_a = names_a.into_iter();
+    let total = match names_a.size_hint() {
+        (lower, Some(upper)) if lower == upper => Some(upper as u64),
+        _ => None,
+    };
+    let mut manifest = Manifest::start(&output_dir, options, total.map(|total| total as usize), candidate_index.candidate_count()).unwrap_or_else(|e| panic!("Error checkpointing into {}: {}", output_dir.display(), e));
+    options.progress.start(total.map(|total| (0..total as usize).filter(|&a_id| !manifest.is_completed(a_id)).count() as u64));
+    let mut is_stopped = false;
+    let mut first_a_id = 0;
+    for chunk in &names_a.chunks(chunk_size) {
+        let chunk = chunk.collect::<Vec<String>>();
+        manifest.check_names_read(first_a_id + chunk.len()).unwrap_or_else(|e| panic!("Error checkpointing into {}: {}", output_dir.display(), e));
+        let names_left = (first_a_id..first_a_id + chunk.len()).filter(|&a_id| !manifest.is_completed(a_id)).count();
+        if names_left > 0 {
+            let completed = match_chunk(candidate_index, &chunk, first_a_id, &output_dir, options, &manifest);
+            manifest.record(&completed).unwrap_or_else(|e| panic!("Error checkpointing into {}: {}", output_dir.display(), e));
+            if completed.len() < names_left {
+                is_stopped = true;
+                break;
+            }
+        }
+        first_a_id += chunk.len();
+    }
+    if !is_stopped {
+        manifest.finish_names_a(first_a_id).unwrap_or_else(|e| panic!("Error checkpointing into {}: {}", output_dir.display(), e));
+    }
+    options.progress.finish();
+    MatchOutcome { completed: manifest.completed(), is_stopped }
+}
+
+/// Matches a chunk of names against the candidates in parallel and writes out their matches,
+/// advancing the progress reporter by every name matched. Returns the indices within all of
+/// names_a of the names whose matches were written, in increasing order, which is every name in
+/// the chunk not already completed unless the options say to stop part way through.
+///
+/// # Arguments
+///
+/// * `candidate_index`: The candidates to match against.
+/// * `names_a`: The chunk of names to match.
+/// * `first_a_id`: The index of the first name of the chunk within all of names_a, which is used
+///   to name the output files.
+/// * `output_dir`: The location of the output directory to write matches to.
+/// * `options`: How to score and write the matches. See `MatchOptions`.
+/// * `manifest`: The names already completed, which are skipped.
+#[cfg(feature = "io")]
+fn match_chunk(candidate_index: &CandidateIndex, names_a: &[String], first_a_id: usize, output_dir: &Path, options: &MatchOptions, manifest: &Manifest) -> Vec<usize> {
+    let lookup_a_by_name = names_a.iter().enumerate().map(|(i, name)| (first_a_id + i, name)).filter(|&(a_id, _)| !manifest.is_completed(a_id)).fold(HashMap::new(), |mut lookup, (a_id, name)|  { 
+        let entry = lookup.entry(name).or_insert_with(Vec::new);
+        entry.push(a_id);
         lookup
     });
-    let mut names_a = names_a.clone();
+    let mut names_a = lookup_a_by_name.keys().map(|name| name.to_string()).collect::<Vec<String>>();
     names_a.sort();
-    names_a.dedup();
     let lookup_a_by_new_id = names_a.iter().enumerate().map(|(i, name)| {
         (i, lookup_a_by_name[name].clone())
     }).collect::<HashMap<_, _>>();
 
-    let lookup_b_by_name = names_b.iter().enumerate().fold(HashMap::new(), |mut lookup, (i, name)|  { 
-        let entry = lookup.entry(name).or_insert(Vec::new());
-        entry.push(i);
-        lookup
-    });
-    let mut names_b = names_b.clone();
-    names_b.sort();
-    names_b.dedup();
-    let lookup_b_by_new_id = names_b.iter().enumerate().map(|(i, name)| {
-        (i, lookup_b_by_name[name].clone())
-    }).collect::<HashMap<_, _>>();
-
-    create_dir_all(&mut output_dir).unwrap();
-    let base_candidate_lookup = build_candidate_lookup(&names_b);
-    let base_candidate_scores = names_b.iter().map(|name| {
-        CandidateScore::new(name.len() as u8)
-    }).collect::<Vec<CandidateScore>>();
-    names_a.par_iter().progress_count(names_a.len() as
-      u64).enumerate().for_each(|(new_a_id, query_name)| {
-        let query_masks_lookup = maskify(&query_name);
-        let query_partial = ((1.0 / query_name.len() as f32) * 1024.0) as u16;
-
-        let mut candidate_scores = base_candidate_scores.clone();
-        for (query_index, (letter_index, query_mask_by_candidate_len)) in query_masks_lookup.iter().enumerate() {
-             base_candidate_lookup[*letter_index as usize].iter().for_each(|c_info| {
-                let candidate_score = &mut candidate_scores[c_info.name_index];
-                let query_mask = query_mask_by_candidate_len[c_info.len - 1];
-                score_letter(candidate_score, query_mask, c_info.mask, query_index);
-             });
+    let mut completed = names_a.par_iter().enumerate().filter_map(|(new_a_id, query_name)| {
+        if options.is_stopped() {
+            return None;
         }
+        let b_matches = candidate_index.matches(query_name, options).unwrap_or_else(|e| panic!("Error matching {:?}: {}", query_name, e));
         let a_ids = lookup_a_by_new_id.get(&new_a_id).unwrap();
-        let mut a_files = a_ids.iter().map(|a_id| {
-            let mut output_path = output_dir.clone();
-            let mut file_name = a_id.to_string();
-            file_name.push_str(".txt");
-            output_path.push(file_name);
-            BufWriter::with_capacity(100000, File::create(output_path).unwrap())
-        }).collect::<Vec<_>>();
-        candidate_scores.into_iter().enumerate().flat_map(|(score_i, score)| {
-            let jw = score.calculate_jaro_winkler(query_partial);
-            if jw >= min_jaro_winkler {
-                Some((score_i, jw))
-            } else { None}
-        }).for_each(|(score_i, jw)| { 
-            //writeln!(file, "{},{:.2}", score_i, jw).unwrap(); 
-            let b_ids = lookup_b_by_new_id.get(&score_i).unwrap();
-            //writeln!(file, "{}", ids.iter().fold(0_usize, |sum, val| sum + val)).unwrap();
-            a_files.iter_mut().for_each(|file| {
-                b_ids.iter().for_each(|id| { writeln!(file, "{},{:.2}", id, jw).unwrap(); }); 
-            });
+        a_ids.iter().for_each(|a_id| {
+            let output_path = output_dir.join(format!("{}.txt", a_id));
+            write_file(&output_path, options.checkpoint != Checkpoint::Off, |file| {
+                b_matches.iter().try_for_each(|(id, jw)| writeln!(file, "{},{:.2}", id, jw))
+            }).unwrap_or_else(|e| panic!("Error writing {}: {}", output_path.display(), e));
         });
-    });
+        options.progress.advance(a_ids.len() as u64);
+        Some(a_ids)
+    }).flat_map_iter(|a_ids| a_ids.iter().copied()).collect::<Vec<usize>>();
+    completed.sort_unstable();
+    completed
 }
 
-
 /// Computing jaro winkler using the strsim library for testing.
+#[cfg(feature = "baselines")]
 #[inline]
-pub fn strsim_jaro_winkler(names_a: &Vec<String>, names_b: &Vec<String>, mut output_dir: PathBuf, min_jaro_winkler: f32) {
+pub fn strsim_jaro_winkler(names_a: &[String], names_b: &[String], mut output_dir: PathBuf, min_jaro_winkler: f32) {
     create_dir_all(&mut output_dir).unwrap();
-    names_a.par_iter().progress_count(names_a.len() as
-      u64).enumerate().for_each(|(i, name_a)| {
+    names_a.par_iter().enumerate().for_each(|(i, name_a)| {
         let mut output_path = output_dir.clone();
         let mut file_name = i.to_string();
         file_name.push_str(".txt");
@@ -264,11 +989,11 @@ pub fn strsim_jaro_winkler(names_a: &Vec<String>, names_b: &Vec<String>, mut out
 }
 
 /// Computing jaro winkler using the eddie library for testing.
+#[cfg(feature = "baselines")]
 #[inline]
-pub fn eddie_jaro_winkler(names_a: &Vec<String>, names_b: &Vec<String>, mut output_dir: PathBuf, min_jaro_winkler: f32) {
+pub fn eddie_jaro_winkler(names_a: &[String], names_b: &[String], mut output_dir: PathBuf, min_jaro_winkler: f32) {
     create_dir_all(&mut output_dir).unwrap();
-    names_a.par_iter().progress_count(names_a.len() as
-      u64).enumerate().for_each(|(i, name_a)| {
+    names_a.par_iter().enumerate().for_each(|(i, name_a)| {
         let mut output_path = output_dir.clone();
         let mut file_name = i.to_string();
         file_name.push_str(".txt");
@@ -287,12 +1012,38 @@ pub fn eddie_jaro_winkler(names_a: &Vec<String>, names_b: &Vec<String>, mut outp
 
 #[cfg(test)]
 mod tests {
-    use crate::pseudo_jaro_winkler;
+    use crate::{is_valid_name, jaro_winkler, max_deviation, maskify, CandidateIndex, EmptyNameError, EmptyNames, Kernel, MatchOptions, MatchOrder, ScoringStrategy};
+    #[cfg(feature = "io")]
+    use crate::{read_names as read_names_from, read_names_or_empty, CancellationToken, Checkpoint, LogProgress, MANIFEST_FILE_NAME, MatchOutcome, ProgressReporter, pseudo_jaro_winkler, pseudo_jaro_winkler_streaming, pseudo_jaro_winkler_sharded, pseudo_jaro_winkler_sharded_streaming, pseudo_jaro_winkler_with_index, pseudo_jaro_winkler_with_options, shard_range};
+    #[cfg(feature = "parallel")]
+    use crate::{evaluate, recall_safe_threshold, EvaluateOptions};
+    #[cfg(feature = "serve")]
+    use crate::{MatchServer, MAX_BODY_BYTES};
+    #[cfg(not(feature = "std"))]
+    use std::{format, vec, string::{String, ToString}, vec::Vec};
+    #[cfg(feature = "io")]
     use serde::{Serialize, Deserialize};
+    #[cfg(feature = "io")]
     use std::path::PathBuf;
-    use std::fs::{read_dir, remove_dir_all};
+    #[cfg(feature = "io")]
+    use std::sync::{Arc, Mutex};
+    #[cfg(feature = "io")]
+    use std::sync::atomic::{AtomicU64, Ordering};
+    #[cfg(feature = "io")]
+    use std::time::{Duration, Instant};
+    #[cfg(feature = "io")]
+    use std::fs::{read_dir, read_to_string, remove_dir_all};
+    use std::fs::File;
+    use std::io::{BufRead, BufReader};
+    use itertools::Itertools;
+    #[cfg(feature = "parallel")]
+    use rayon::prelude::*;
+    #[cfg(feature = "parallel")]
     use statistical::*;
+    use proptest::prelude::*;
+    use proptest::collection::vec;
 
+    #[cfg(feature = "io")]
     #[derive(Serialize, Deserialize, Debug)]
     struct NameRec {
         histid: String,
@@ -305,6 +1056,7 @@ mod tests {
         last_name: String,
     }
 
+    #[cfg(feature = "io")]
     #[derive(Serialize, Deserialize, Debug)]
     struct ResultRec {
         id: String,
@@ -318,7 +1070,13 @@ mod tests {
     /// randomly from 1880 to be no greater than 0.002 with a standard deviation of no greater 
     /// than 0.01. It also makes sure that errors which are greater than 0.02 are less 
     /// than 2% of all the winklers calculated.
+    ///
+    /// The 1880 names in `tests/input/` aren't in the repository, so this is ignored unless run
+    /// with `--ignored` once they are in place. The property tests below check the same against
+    /// strsim on generated names.
+    #[cfg(feature = "io")]
     #[test]
+    #[ignore = "needs tests/input/prepped_df_*.csv"]
     fn test_batch() {
         let query_names = csv::ReaderBuilder::new().has_headers(false).from_path("./tests/input/prepped_df_b.csv").unwrap().deserialize().map(|rec| {
             let rec: NameRec = rec.unwrap();
@@ -345,7 +1103,7 @@ mod tests {
             output_results.sort_by(|result_a, result_b| result_a.id.cmp(&result_b.id));
             let mut answer_results = answer_reader.deserialize().map(|rec| rec.unwrap()).collect::<Vec<ResultRec>>();
             answer_results.sort_by(|result_a, result_b| result_a.id.cmp(&result_b.id));
-            output_results.iter().zip(answer_results.iter()).filter(|(_, a_result)| a_result.jw > 0.7).map(|(o_result, a_result)| { (o_result.jw -a_result.jw).abs() as f64 }).collect::<Vec<f64>>()
+            output_results.iter().zip(answer_results.iter()).filter(|(_, a_result)| a_result.jw > 0.7).map(|(o_result, a_result)| { (o_result.jw -a_result.jw).abs() }).collect::<Vec<f64>>()
         }).collect::<Vec<f64>>();
         let mean_error: f64 = mean(errors.as_slice());
         let std_dev = standard_deviation(errors.as_slice(), Some(mean_error));
@@ -356,4 +1114,768 @@ mod tests {
         assert!(errors_over_two_points_off < 0.02);
         remove_dir_all(output_dir.clone()).unwrap();
     }
+
+    fn read_names(path: &str) -> Vec<String> {
+        BufReader::new(File::open(path).unwrap()).lines().map(|n| n.unwrap()).collect()
+    }
+
+    /// Makes sure that only scoring the candidates which share one of the query's rarest letters
+    /// finds exactly the same matches as scoring every letter.
+    #[test]
+    fn test_rare_letters_first() {
+        let query_names = read_names("./input/file_a_small.txt");
+        let candidate_index = CandidateIndex::new(&read_names("./input/file_b.txt"));
+        for min_jaro_winkler in [0.0, 0.7, 0.8, 0.9, 1.1] {
+            query_names.iter().take(100).for_each(|query_name| {
+                let all_letters = candidate_index.score(query_name, min_jaro_winkler, ScoringStrategy::AllLetters, Kernel::Scalar);
+                let rare_letters_first = candidate_index.score(query_name, min_jaro_winkler, ScoringStrategy::RareLettersFirst, Kernel::Scalar);
+                assert_eq!(all_letters, rare_letters_first, "query: {}, min_jaro_winkler: {}", query_name, min_jaro_winkler);
+            });
+        }
+    }
+
+    /// Makes sure that the SIMD kernels, and the lane by lane version they are based on, find
+    /// exactly the same scores as the scalar kernel.
+    #[test]
+    fn test_kernels() {
+        let query_names = read_names("./input/file_a_small.txt");
+        let candidate_index = CandidateIndex::new(&read_names("./input/file_b.txt"));
+        for min_jaro_winkler in [0.0, 0.8] {
+            query_names.iter().take(100).for_each(|query_name| {
+                let scalar = candidate_index.score(query_name, min_jaro_winkler, ScoringStrategy::AllLetters, Kernel::Scalar);
+                let query_partial = ((1.0 / query_name.len() as f32) * 1024.0) as u16;
+                let lanes = unsafe { candidate_index.dense_candidates.score(&maskify(query_name), query_partial, min_jaro_winkler, Kernel::Scalar) };
+                assert_eq!(scalar, lanes, "query: {}, min_jaro_winkler: {}", query_name, min_jaro_winkler);
+                [Kernel::Sse41, Kernel::Avx2].iter().filter(|kernel| kernel.is_available()).for_each(|&kernel| {
+                    let simd = candidate_index.score(query_name, min_jaro_winkler, ScoringStrategy::AllLetters, kernel);
+                    assert_eq!(scalar, simd, "query: {}, min_jaro_winkler: {}, kernel: {:?}", query_name, min_jaro_winkler, kernel);
+                });
+            });
+        }
+    }
+
+    /// Makes sure that matching a single name keeps the matches reaching the threshold, in the
+    /// order asked for, without needing any of the features for matching files.
+    #[test]
+    fn test_matches() {
+        let candidate_names = ["ellie", "nellie", "ellen", "john", "jon", "ellie", "elly"].iter().map(|name| name.to_string()).collect::<Vec<String>>();
+        let candidate_index = CandidateIndex::new(&candidate_names);
+        let by_candidate = candidate_index.matches("ellie", &MatchOptions { order: MatchOrder::ByCandidate, ..MatchOptions::new(0.8) }).unwrap();
+        assert_eq!(by_candidate.iter().map(|&(b_id, _)| b_id).collect::<Vec<_>>(), vec![0, 1, 2, 5, 6]);
+        assert!(by_candidate[0].1 > 0.99 && by_candidate[0].1 == by_candidate[3].1, "{:?}", by_candidate);
+        let mut unsorted = candidate_index.matches("ellie", &MatchOptions::new(0.8)).unwrap();
+        unsorted.sort_by_key(|&(b_id, _)| b_id);
+        assert_eq!(unsorted, by_candidate);
+
+        let by_score = candidate_index.matches("ellie", &MatchOptions { order: MatchOrder::ByScore, ..MatchOptions::new(0.8) }).unwrap();
+        assert_eq!(by_score.iter().map(|&(b_id, _)| b_id).sorted().collect::<Vec<_>>(), vec![0, 1, 2, 5, 6]);
+        assert!(by_score.windows(2).all(|pair| pair[0].1 >= pair[1].1), "{:?}", by_score);
+        assert_eq!((by_score[0].0, by_score[1].0), (0, 5));
+        [MatchOrder::Unsorted, MatchOrder::ByScore].iter().for_each(|&order| {
+            let top_k = candidate_index.matches("ellie", &MatchOptions { top_k: Some(3), order, ..MatchOptions::new(0.8) }).unwrap();
+            assert_eq!(top_k, by_score[..3], "{:?}", order);
+        });
+        let top_k = candidate_index.matches("ellie", &MatchOptions { top_k: Some(3), order: MatchOrder::ByCandidate, ..MatchOptions::new(0.8) }).unwrap();
+        assert_eq!(top_k, by_score[..3].iter().copied().sorted_by_key(|&(b_id, _)| b_id).collect::<Vec<_>>());
+
+        let verified = candidate_index.matches("jon", &MatchOptions { verify_margin: Some(1.0), order: MatchOrder::ByCandidate, ..MatchOptions::new(0.7) }).unwrap();
+        let expected = candidate_names.iter().map(|name| strsim::jaro_winkler("jon", name) as f32).enumerate().filter(|&(_, jw)| jw >= 0.7).collect::<Vec<_>>();
+        assert_eq!(verified, expected);
+        assert!(candidate_index.matches("ellie", &MatchOptions::new(1.1)).unwrap().is_empty());
+        assert!(candidate_index.matches("Ellie", &MatchOptions::new(0.0)).unwrap().is_empty());
+    }
+
+    /// Makes sure that streaming the names in chunks writes exactly the same files as matching
+    /// them all at once, including names which are repeated across chunks.
+    #[cfg(feature = "io")]
+    #[test]
+    fn test_streaming() {
+        let query_names = read_names("./input/file_a_small.txt").into_iter().take(200).collect::<Vec<String>>();
+        let candidate_names = read_names("./input/file_b.txt");
+        let output_dir = std::env::temp_dir().join("pseudo_jaro_winkler_test_streaming");
+        let streamed_output_dir = std::env::temp_dir().join("pseudo_jaro_winkler_test_streaming_chunks");
+        remove_dir_all(&output_dir).ok();
+        remove_dir_all(&streamed_output_dir).ok();
+        pseudo_jaro_winkler(&query_names, &candidate_names, output_dir.clone(), 0.8);
+        pseudo_jaro_winkler_streaming(query_names.clone(), &candidate_names, streamed_output_dir.clone(), &MatchOptions::new(0.8), 7);
+        assert_eq!(read_dir(&streamed_output_dir).unwrap().count(), query_names.len());
+        (0..query_names.len()).for_each(|a_id| {
+            let file_name = format!("{}.txt", a_id);
+            assert_eq!(read_to_string(output_dir.join(&file_name)).unwrap(), read_to_string(streamed_output_dir.join(&file_name)).unwrap(), "file: {}", file_name);
+        });
+        remove_dir_all(&output_dir).unwrap();
+        remove_dir_all(&streamed_output_dir).unwrap();
+    }
+
+    /// Counts the calls made to a reporter.
+    #[cfg(feature = "io")]
+    #[derive(Default)]
+    struct CountingProgress {
+        totals: Mutex<Vec<Option<u64>>>,
+        advanced: AtomicU64,
+        finished: AtomicU64,
+    }
+
+    #[cfg(feature = "io")]
+    impl ProgressReporter for CountingProgress {
+        fn start(&self, total: Option<u64>) {
+            self.totals.lock().unwrap().push(total);
+        }
+
+        fn advance(&self, count: u64) {
+            self.advanced.fetch_add(count, Ordering::Relaxed);
+        }
+
+        fn finish(&self) {
+            self.finished.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    #[cfg(feature = "io")]
+    #[test]
+    fn test_progress() {
+        let query_names = read_names("./input/file_a_small.txt").into_iter().take(50).collect::<Vec<String>>();
+        let candidate_names = read_names("./input/file_b.txt");
+        let output_dir = std::env::temp_dir().join("pseudo_jaro_winkler_test_progress");
+        remove_dir_all(&output_dir).ok();
+        let progress = Arc::new(CountingProgress::default());
+        let options = MatchOptions { progress: progress.clone(), ..MatchOptions::new(0.8) };
+        pseudo_jaro_winkler_with_options(&query_names, &candidate_names, output_dir.clone(), &options);
+        assert_eq!(*progress.totals.lock().unwrap(), vec![Some(query_names.len() as u64)]);
+        assert_eq!(progress.advanced.load(Ordering::Relaxed), query_names.len() as u64);
+        assert_eq!(progress.finished.load(Ordering::Relaxed), 1);
+        remove_dir_all(&output_dir).unwrap();
+
+        let lines = Arc::new(Mutex::new(Vec::new()));
+        let sink_lines = lines.clone();
+        let log = LogProgress::with_sink(Duration::from_secs(0), move |line| sink_lines.lock().unwrap().push(line.to_string()));
+        log.start(Some(4));
+        log.advance(1);
+        log.advance(3);
+        log.finish();
+        let lines = lines.lock().unwrap();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].starts_with("Matched 1/4 names (25.0%)"), "{}", lines[0]);
+        assert!(lines[2].starts_with("Matched 4 names in "), "{}", lines[2]);
+    }
+
+    /// Cancels a token once a number of names have been matched.
+    #[cfg(feature = "io")]
+    struct CancelAfter {
+        count: u64,
+        advanced: AtomicU64,
+        cancellation: CancellationToken,
+    }
+
+    #[cfg(feature = "io")]
+    impl ProgressReporter for CancelAfter {
+        fn start(&self, _total: Option<u64>) {}
+
+        fn advance(&self, count: u64) {
+            if self.advanced.fetch_add(count, Ordering::Relaxed) + count >= self.count {
+                self.cancellation.cancel();
+            }
+        }
+
+        fn finish(&self) {}
+    }
+
+    /// Makes sure that a stopped run writes the complete output files of exactly the names it says
+    /// it completed.
+    #[cfg(feature = "io")]
+    #[test]
+    fn test_cancellation() {
+        let query_names = read_names("./input/file_a_small.txt");
+        let candidate_names = read_names("./input/file_b.txt");
+        let output_dir = std::env::temp_dir().join("pseudo_jaro_winkler_test_cancellation");
+        let stopped_output_dir = std::env::temp_dir().join("pseudo_jaro_winkler_test_cancellation_stopped");
+        remove_dir_all(&output_dir).ok();
+        remove_dir_all(&stopped_output_dir).ok();
+        let outcome = pseudo_jaro_winkler_with_options(&query_names, &candidate_names, output_dir.clone(), &MatchOptions::new(0.8));
+        assert_eq!(outcome, MatchOutcome { completed: std::iter::once(0..query_names.len()).collect(), is_stopped: false });
+
+        let options = MatchOptions { deadline: Some(Instant::now()), ..MatchOptions::new(0.8) };
+        let outcome = pseudo_jaro_winkler_streaming(query_names.clone(), &candidate_names, stopped_output_dir.clone(), &options, 7);
+        assert_eq!(outcome, MatchOutcome { completed: vec![], is_stopped: true });
+        assert_eq!(read_dir(&stopped_output_dir).unwrap().count(), 0);
+
+        let mut options = MatchOptions::new(0.8);
+        options.progress = Arc::new(CancelAfter { count: 30, advanced: AtomicU64::new(0), cancellation: options.cancellation.clone() });
+        let outcome = pseudo_jaro_winkler_streaming(query_names.clone(), &candidate_names, stopped_output_dir.clone(), &options, 7);
+        assert!(outcome.is_stopped);
+        assert!((30..query_names.len()).contains(&outcome.completed_count()), "completed {} names", outcome.completed_count());
+        assert!(outcome.completed.windows(2).all(|pair| pair[0].end < pair[1].start), "{:?}", outcome.completed);
+        assert_eq!(read_dir(&stopped_output_dir).unwrap().count(), outcome.completed_count());
+        outcome.completed.iter().flat_map(std::ops::Range::clone).for_each(|a_id| {
+            let file_name = format!("{}.txt", a_id);
+            assert_eq!(read_to_string(output_dir.join(&file_name)).unwrap(), read_to_string(stopped_output_dir.join(&file_name)).unwrap(), "file: {}", file_name);
+        });
+        remove_dir_all(&output_dir).unwrap();
+        remove_dir_all(&stopped_output_dir).unwrap();
+    }
+
+    /// Makes sure that resuming a stopped run only matches the names it didn't write, and writes
+    /// exactly the same output files as a run that was never stopped.
+    #[cfg(feature = "io")]
+    #[test]
+    fn test_checkpoint() {
+        let query_names = read_names("./input/file_a_small.txt");
+        let candidate_names = read_names("./input/file_b.txt");
+        let output_dir = std::env::temp_dir().join("pseudo_jaro_winkler_test_checkpoint");
+        let resumed_output_dir = std::env::temp_dir().join("pseudo_jaro_winkler_test_checkpoint_resumed");
+        remove_dir_all(&output_dir).ok();
+        remove_dir_all(&resumed_output_dir).ok();
+        pseudo_jaro_winkler(&query_names, &candidate_names, output_dir.clone(), 0.8);
+
+        let mut options = MatchOptions { checkpoint: Checkpoint::Write, ..MatchOptions::new(0.8) };
+        options.progress = Arc::new(CancelAfter { count: 30, advanced: AtomicU64::new(0), cancellation: options.cancellation.clone() });
+        let stopped = pseudo_jaro_winkler_streaming(query_names.clone(), &candidate_names, resumed_output_dir.clone(), &options, 7);
+        assert!(stopped.is_stopped);
+        assert!(resumed_output_dir.join(MANIFEST_FILE_NAME).exists());
+
+        let progress = Arc::new(CountingProgress::default());
+        let options = MatchOptions { checkpoint: Checkpoint::Resume, progress: progress.clone(), ..MatchOptions::new(0.8) };
+        let outcome = pseudo_jaro_winkler_streaming(query_names.clone(), &candidate_names, resumed_output_dir.clone(), &options, 7);
+        assert_eq!(outcome, MatchOutcome { completed: std::iter::once(0..query_names.len()).collect(), is_stopped: false });
+        assert_eq!(progress.advanced.load(Ordering::Relaxed), (query_names.len() - stopped.completed_count()) as u64);
+        assert_eq!(read_dir(&resumed_output_dir).unwrap().count(), query_names.len() + 1);
+        (0..query_names.len()).for_each(|a_id| {
+            let file_name = format!("{}.txt", a_id);
+            assert_eq!(read_to_string(output_dir.join(&file_name)).unwrap(), read_to_string(resumed_output_dir.join(&file_name)).unwrap(), "file: {}", file_name);
+        });
+        assert_eq!(read_to_string(resumed_output_dir.join(MANIFEST_FILE_NAME)).unwrap(), format!("pseudo_jaro_winkler manifest 1\nmin_jaro_winkler 0.8\ntop_k none\norder unsorted\nverify_margin none\nrecall_safe false\nempty_names skip\nnames_a {}\ncandidates {}\ncompleted 0..{}\n", query_names.len(), candidate_names.len(), query_names.len()));
+
+        let options = MatchOptions { checkpoint: Checkpoint::Resume, top_k: Some(3), ..MatchOptions::new(0.8) };
+        assert!(std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| pseudo_jaro_winkler_with_options(&query_names, &candidate_names, resumed_output_dir.clone(), &options))).is_err());
+        // Resuming with different inputs fails, even when their length is only known at the end
+        let options = MatchOptions { checkpoint: Checkpoint::Resume, ..MatchOptions::new(0.8) };
+        assert!(std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| pseudo_jaro_winkler_with_options(&query_names, &candidate_names[1..], resumed_output_dir.clone(), &options))).is_err());
+        assert!(std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| pseudo_jaro_winkler_with_options(&query_names[1..], &candidate_names, resumed_output_dir.clone(), &options))).is_err());
+        // Filtering hides the stream's length until it ends
+        let longer_query_names = query_names.iter().chain(&query_names[..1]).filter(|_| true).cloned();
+        assert!(std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| pseudo_jaro_winkler_streaming(longer_query_names, &candidate_names, resumed_output_dir.clone(), &options, 7))).is_err());
+        remove_dir_all(&output_dir).unwrap();
+        remove_dir_all(&resumed_output_dir).unwrap();
+    }
+
+    /// Makes sure that evaluating the pseudo scores counts the same pairs as comparing them with
+    /// strsim directly.
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn test_evaluate() {
+        let query_names = read_names("./input/file_a_small.txt").into_iter().take(50).collect::<Vec<String>>();
+        let candidate_names = read_names("./input/file_b.txt");
+        let evaluation = evaluate(&query_names, &candidate_names, &EvaluateOptions::new(0.8));
+        let candidate_index = CandidateIndex::new(&candidate_names);
+        let pairs = query_names.iter().flat_map(|query_name| {
+            let pseudo_scores = candidate_index.matches(query_name, &MatchOptions::new(0.0)).unwrap().into_iter().collect::<std::collections::HashMap<_, _>>();
+            candidate_names.iter().enumerate().map(move |(b_id, name)| (pseudo_scores.get(&b_id).copied().unwrap_or(0.0), strsim::jaro_winkler(query_name, name)))
+        }).collect::<Vec<_>>();
+        assert_eq!(evaluation.true_positives, pairs.iter().filter(|&&(pseudo, exact)| pseudo >= 0.8 && exact as f32 >= 0.8).count());
+        assert_eq!(evaluation.false_positives, pairs.iter().filter(|&&(pseudo, exact)| pseudo >= 0.8 && (exact as f32) < 0.8).count());
+        assert_eq!(evaluation.false_negatives, pairs.iter().filter(|&&(pseudo, exact)| pseudo < 0.8 && exact as f32 >= 0.8).count());
+        assert!(evaluation.precision() > 0.99 && evaluation.recall() > 0.95);
+        let errors = pairs.iter().filter(|&&(pseudo, exact)| pseudo as f64 >= 0.7 || exact >= 0.7).map(|&(pseudo, exact)| (pseudo as f64 - exact).abs()).collect::<Vec<f64>>();
+        assert_eq!(evaluation.error_count, errors.len());
+        assert_eq!(evaluation.histogram.iter().sum::<usize>(), errors.len());
+        assert!((evaluation.mean_error - mean(&errors)).abs() < 1e-9);
+        assert!((evaluation.std_dev_error - population_standard_deviation(&errors, None)).abs() < 1e-6);
+        assert_eq!(evaluation.worst_pairs.len(), 10);
+        assert_eq!(evaluation.worst_pairs[0].error(), evaluation.max_error);
+        assert!(evaluation.worst_pairs.windows(2).all(|pair| pair[0].error() >= pair[1].error()));
+        evaluation.worst_pairs.iter().for_each(|pair| {
+            assert_eq!((pair.name_a.as_str(), pair.name_b.as_str()), (query_names[pair.a_id].as_str(), candidate_names[pair.b_id].as_str()));
+            assert_eq!(pair.exact, strsim::jaro_winkler(&pair.name_a, &pair.name_b));
+        });
+    }
+
+    /// Makes sure that the exact jaro winkler scores like strsim, and that verifying every candidate
+    /// finds exactly the matches strsim does.
+    #[test]
+    fn test_verify_margin() {
+        let query_names = read_names("./input/file_a_small.txt").into_iter().take(50).collect::<Vec<String>>();
+        let candidate_names = read_names("./input/file_b.txt");
+        query_names.iter().for_each(|query_name| {
+            candidate_names.iter().take(2000).for_each(|name| assert_eq!(jaro_winkler(query_name, name), strsim::jaro_winkler(query_name, name), "{} {}", query_name, name));
+        });
+        assert_eq!(jaro_winkler("", ""), strsim::jaro_winkler("", ""));
+        assert_eq!(jaro_winkler("a", ""), strsim::jaro_winkler("a", ""));
+
+        let candidate_index = CandidateIndex::new(&candidate_names);
+        [(0.8, 0.8), (0.9, 0.05)].iter().for_each(|&(min_jaro_winkler, verify_margin)| {
+            let options = MatchOptions { verify_margin: Some(verify_margin), ..MatchOptions::new(min_jaro_winkler) };
+            let pseudo_options = MatchOptions { min_jaro_winkler: min_jaro_winkler - verify_margin, ..MatchOptions::new(0.0) };
+            query_names.iter().for_each(|query_name| {
+                let mut matches = candidate_index.matches(query_name, &options).unwrap();
+                matches.sort_by_key(|&(b_id, _)| b_id);
+                let pseudo_scores = candidate_index.matches(query_name, &pseudo_options).unwrap().into_iter().collect::<std::collections::HashMap<_, _>>();
+                let expected = candidate_names.iter().enumerate().flat_map(|(b_id, name)| {
+                    let pseudo_score = *pseudo_scores.get(&b_id)?;
+                    let jw = if pseudo_score < min_jaro_winkler + verify_margin { strsim::jaro_winkler(query_name, name) as f32 } else { pseudo_score };
+                    if jw >= min_jaro_winkler { Some((b_id, jw)) } else { None }
+                }).collect::<Vec<_>>();
+                assert_eq!(matches, expected, "query: {}", query_name);
+                if verify_margin >= min_jaro_winkler {
+                    let strsim_matches = candidate_names.iter().enumerate().map(|(b_id, name)| (b_id, strsim::jaro_winkler(query_name, name) as f32)).filter(|&(_, jw)| jw >= min_jaro_winkler).collect::<Vec<_>>();
+                    assert_eq!(matches, strsim_matches, "query: {}", query_name);
+                }
+            });
+        });
+    }
+
+    /// Makes sure that the recall-safe threshold is never above the pseudo score of a candidate
+    /// whose exact jaro winkler reaches the threshold, and that recall-safe matching finds exactly
+    /// the matches strsim does.
+    #[cfg(feature = "parallel")]
+    fn check_recall_safe(query_names: &[String], candidate_names: &[String]) {
+        let candidate_index = CandidateIndex::new(candidate_names);
+        query_names.par_iter().for_each(|query_name| {
+            let pseudo_scores = candidate_index.matches(query_name, &MatchOptions::new(0.0)).unwrap().into_iter().collect::<std::collections::HashMap<_, _>>();
+            let exact_scores = candidate_names.iter().map(|name| strsim::jaro_winkler(query_name, name) as f32).collect::<Vec<_>>();
+            [0.7, 0.8, 0.9].iter().for_each(|&min_jaro_winkler| {
+                let min_pseudo_score = recall_safe_threshold(query_name.len(), min_jaro_winkler);
+                exact_scores.iter().enumerate().filter(|&(_, &jw)| jw >= min_jaro_winkler).for_each(|(b_id, _)| {
+                    let pseudo_score = pseudo_scores.get(&b_id).copied().unwrap_or(0.0);
+                    assert!(pseudo_score >= min_pseudo_score, "{} {}: {} < {}", query_name, candidate_names[b_id], pseudo_score, min_pseudo_score);
+                });
+                let options = MatchOptions { recall_safe: true, order: MatchOrder::ByCandidate, ..MatchOptions::new(min_jaro_winkler) };
+                let expected = exact_scores.iter().copied().enumerate().filter(|&(_, jw)| jw >= min_jaro_winkler).collect::<Vec<_>>();
+                assert_eq!(candidate_index.matches(query_name, &options).unwrap(), expected, "query: {}", query_name);
+            });
+        });
+    }
+
+    /// Checks recall-safe matching for every pair of names in the small query files and the full
+    /// candidate files. Only the small files are used to keep the tests quick, so this samples the
+    /// names in `input/`, see `test_recall_safe_exhaustive`.
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn test_recall_safe() {
+        assert!(max_deviation() > 0.2 && max_deviation() < 0.3);
+        [("./input/file_a_small.txt", "./input/file_b.txt"), ("./input/lnames_a_small.txt", "./input/lnames_b.txt")].iter().for_each(|&(query_path, candidate_path)| {
+            check_recall_safe(&read_names(query_path).into_iter().unique().collect::<Vec<String>>(), &read_names(candidate_path));
+        });
+    }
+
+    /// Checks recall-safe matching for every pair of distinct names in the full query and candidate
+    /// files, which takes too long to run with the other tests. Run it with
+    /// `cargo test --release -- --ignored test_recall_safe_exhaustive`.
+    #[cfg(feature = "parallel")]
+    #[test]
+    #[ignore]
+    fn test_recall_safe_exhaustive() {
+        [("./input/file_a.txt", "./input/file_b.txt"), ("./input/lnames_a.txt", "./input/lnames_b.txt")].iter().for_each(|&(query_path, candidate_path)| {
+            check_recall_safe(&read_names(query_path).into_iter().unique().collect::<Vec<String>>(), &read_names(candidate_path).into_iter().unique().collect::<Vec<String>>());
+        });
+    }
+
+    /// Regressions found by the fuzz targets in `fuzz/`. Invalid names used to panic while
+    /// building indexes and scoring, and scoring used to shift past the end of a u16 whenever a
+    /// letter didn't match, which panics with the overflow checks that tests run with.
+    #[test]
+    fn test_fuzz_regressions() {
+        let names = ["anna", "Anna", "", "abcdefghijklmnopq", "zo\u{eb}", "a\tb", "ann"].iter().map(|name| name.to_string()).collect::<Vec<String>>();
+        let mut candidate_index = CandidateIndex::new(&names);
+        candidate_index.insert(7, "Ann");
+        names.iter().chain(["Ann".to_string()].iter()).for_each(|name| {
+            let matches = candidate_index.matches(name, &MatchOptions::new(0.0)).unwrap();
+            if is_valid_name(name) {
+                assert_eq!(matches.iter().map(|&(b_id, _)| b_id).sorted().collect::<Vec<_>>(), vec![0, 6], "{:?}", name);
+                assert!(matches.iter().all(|&(_, jw)| (0.0..=1.0).contains(&jw)), "{:?}: {:?}", name, matches);
+            } else {
+                assert_eq!(matches, vec![], "{:?}", name);
+            }
+        });
+        [Kernel::Scalar, Kernel::Sse41, Kernel::Avx2].iter().filter(|kernel| kernel.is_available()).for_each(|&kernel| {
+            assert_eq!(CandidateIndex::new(&["abc".to_string()]).matches("xyz", &MatchOptions { kernel, ..MatchOptions::new(0.0) }).unwrap(), vec![(0, 0.0)]);
+        });
+        #[cfg(feature = "io")]
+        {
+            let read = read_names_from("anna\nbob\nBob\n\nann".as_bytes()).map(|name| name.map_err(|e| e.to_string())).collect::<Vec<_>>();
+            assert_eq!(read[..2], [Ok("anna".to_string()), Ok("bob".to_string())]);
+            assert!(read[2].as_ref().unwrap_err().starts_with("line 3: \"Bob\""), "{:?}", read[2]);
+            assert!(read[3].as_ref().unwrap_err().starts_with("line 4: \"\""), "{:?}", read[3]);
+            assert_eq!(read[4], Ok("ann".to_string()));
+        }
+    }
+
+    /// Makes sure that candidates sharing no letters with a query score exactly 0.0, the same as
+    /// strsim, rather than NaN, so that a threshold of 0.0 keeps every candidate whichever way
+    /// they are scored, and writes the same candidates as strsim does.
+    #[test]
+    fn test_no_shared_letters() {
+        let candidate_names = ["bob", "anna", "xyz", "ann"].iter().map(|name| name.to_string()).collect::<Vec<String>>();
+        let mut candidate_index = CandidateIndex::new(&candidate_names);
+        candidate_index.insert(4, "kurt");
+        let all_names = ["bob", "anna", "xyz", "ann", "kurt"];
+        [ScoringStrategy::AllLetters, ScoringStrategy::RareLettersFirst].iter().for_each(|&strategy| {
+            [Kernel::Scalar, Kernel::Sse41, Kernel::Avx2].iter().filter(|kernel| kernel.is_available()).for_each(|&kernel| {
+                let options = MatchOptions { strategy, kernel, order: MatchOrder::ByCandidate, ..MatchOptions::new(0.0) };
+                let matches = candidate_index.matches("anna", &options).unwrap();
+                assert_eq!(matches.iter().map(|&(b_id, _)| b_id).collect::<Vec<_>>(), vec![0, 1, 2, 3, 4], "{:?} {:?}", strategy, kernel);
+                [0, 2, 4].iter().for_each(|&b_id| {
+                    assert_eq!(matches[b_id].1, 0.0);
+                    assert_eq!(strsim::jaro_winkler("anna", all_names[b_id]), 0.0);
+                });
+                assert!(candidate_index.matches("anna", &MatchOptions { min_jaro_winkler: f32::MIN_POSITIVE, ..options }).unwrap().iter().all(|&(b_id, _)| b_id == 1 || b_id == 3));
+            });
+        });
+
+        #[cfg(feature = "io")]
+        {
+            let query_names = read_names("./input/file_a_small.txt").into_iter().take(20).collect::<Vec<String>>();
+            let candidate_names = read_names("./input/file_b.txt").into_iter().take(2000).collect::<Vec<String>>();
+            let output_dir = std::env::temp_dir().join("pseudo_jaro_winkler_test_no_shared_letters");
+            remove_dir_all(&output_dir).ok();
+            pseudo_jaro_winkler(&query_names, &candidate_names, output_dir.clone(), 0.0);
+            query_names.iter().enumerate().for_each(|(a_id, query_name)| {
+                let file_name = format!("{}.txt", a_id);
+                let b_ids = read_names(output_dir.join(&file_name).to_str().unwrap()).iter().map(|line| line.split(',').next().unwrap().parse::<usize>().unwrap()).sorted().collect::<Vec<_>>();
+                let strsim_b_ids = candidate_names.iter().positions(|name| strsim::jaro_winkler(query_name, name) >= 0.0).collect::<Vec<_>>();
+                assert_eq!(strsim_b_ids.len(), candidate_names.len());
+                assert_eq!(b_ids, strsim_b_ids, "file: {}", file_name);
+            });
+            remove_dir_all(&output_dir).unwrap();
+        }
+    }
+
+    /// Names over the whole alphabet, and names over a few letters and spaces so that letters
+    /// repeat and match out of order. '`' is left out since the pseudo score treats it as a space,
+    /// unlike strsim.
+    fn arb_name() -> impl Strategy<Value = String> {
+        prop_oneof!["[a-z ]{1,16}", "[abe ]{1,16}"]
+    }
+
+    proptest! {
+        /// Makes sure that the exact jaro winkler scores exactly like strsim.
+        #[test]
+        fn prop_exact_matches_strsim(a in arb_name(), b in arb_name()) {
+            prop_assert_eq!(jaro_winkler(&a, &b), strsim::jaro_winkler(&a, &b));
+        }
+
+        /// Makes sure that the pseudo score is never more than `max_deviation` below strsim, nor
+        /// more than 1/9 above it, which is the most that taking off a transposition can add, and
+        /// that every candidate is scored at a threshold of 0.0, with exactly 0.0 when they share
+        /// no matching letters.
+        #[test]
+        fn prop_pseudo_within_bounds(query in arb_name(), candidate_names in vec(arb_name(), 1..20)) {
+            let candidate_index = CandidateIndex::new(&candidate_names);
+            let pseudo_scores = candidate_index.matches(&query, &MatchOptions { order: MatchOrder::ByCandidate, ..MatchOptions::new(0.0) }).unwrap();
+            prop_assert_eq!(pseudo_scores.iter().map(|&(b_id, _)| b_id).collect::<Vec<_>>(), (0..candidate_names.len()).collect::<Vec<_>>());
+            for (name, &(_, pseudo)) in candidate_names.iter().zip(&pseudo_scores) {
+                let exact = strsim::jaro_winkler(&query, name);
+                prop_assert!(exact - pseudo as f64 <= max_deviation() + 1e-6, "{} {}: pseudo {} exact {}", query, name, pseudo, exact);
+                prop_assert!(pseudo as f64 - exact <= 1.0 / 9.0 + 1e-6, "{} {}: pseudo {} exact {}", query, name, pseudo, exact);
+                prop_assert_eq!(pseudo == 0.0, exact == 0.0, "{} {}: pseudo {} exact {}", query, name, pseudo, exact);
+            }
+        }
+
+        /// Makes sure that recall-safe matching, and verifying every candidate, find exactly the
+        /// matches strsim does.
+        #[test]
+        fn prop_verified_matches_strsim(query in arb_name(), candidate_names in vec(arb_name(), 1..20), min_jaro_winkler in 0.5f32..1.0) {
+            let candidate_index = CandidateIndex::new(&candidate_names);
+            let expected = candidate_names.iter().map(|name| strsim::jaro_winkler(&query, name) as f32).enumerate().filter(|&(_, jw)| jw >= min_jaro_winkler).collect::<Vec<_>>();
+            let options = MatchOptions { order: MatchOrder::ByCandidate, ..MatchOptions::new(min_jaro_winkler) };
+            prop_assert_eq!(candidate_index.matches(&query, &MatchOptions { recall_safe: true, ..options.clone() }).unwrap(), expected.clone());
+            prop_assert_eq!(candidate_index.matches(&query, &MatchOptions { verify_margin: Some(1.0), ..options }).unwrap(), expected);
+        }
+    }
+
+    #[cfg(feature = "io")]
+    #[test]
+    fn test_sharded() {
+        assert_eq!((0..4).map(|shard| shard_range(10, shard, 4)).collect::<Vec<_>>(), vec![0..2, 2..5, 5..7, 7..10]);
+        let query_names = read_names("./input/file_a_small.txt").into_iter().take(200).collect::<Vec<String>>();
+        let candidate_names = read_names("./input/file_b.txt");
+        [(None, MatchOrder::Unsorted), (Some(3), MatchOrder::Unsorted), (None, MatchOrder::ByScore), (Some(3), MatchOrder::ByCandidate)].iter().for_each(|&(top_k, order)| {
+            let options = MatchOptions { top_k, order, ..MatchOptions::new(0.8) };
+            let output_dir = std::env::temp_dir().join("pseudo_jaro_winkler_test_sharded");
+            let sharded_output_dir = std::env::temp_dir().join("pseudo_jaro_winkler_test_sharded_shards");
+            remove_dir_all(&output_dir).ok();
+            remove_dir_all(&sharded_output_dir).ok();
+            pseudo_jaro_winkler_with_options(&query_names, &candidate_names, output_dir.clone(), &options);
+            pseudo_jaro_winkler_sharded(&query_names, &candidate_names, sharded_output_dir.clone(), &options, 3);
+            assert_eq!(read_dir(&sharded_output_dir).unwrap().count(), query_names.len());
+            (0..query_names.len()).for_each(|a_id| {
+                let file_name = format!("{}.txt", a_id);
+                let output = read_to_string(output_dir.join(&file_name)).unwrap();
+                let sharded_output = read_to_string(sharded_output_dir.join(&file_name)).unwrap();
+                let matches = output.lines().map(|line| {
+                    let (b_id, jw) = line.split_once(',').unwrap();
+                    (b_id.parse::<usize>().unwrap(), jw.to_string())
+                }).collect::<Vec<_>>();
+                match order {
+                    MatchOrder::ByScore => assert!(matches.windows(2).all(|pair| (&pair[1].1, pair[0].0) < (&pair[0].1, pair[1].0)), "file: {}", file_name),
+                    MatchOrder::ByCandidate => assert!(matches.windows(2).all(|pair| pair[0].0 < pair[1].0), "file: {}", file_name),
+                    MatchOrder::Unsorted => {},
+                }
+                if top_k.is_some() || order != MatchOrder::Unsorted {
+                    assert_eq!(output, sharded_output, "file: {}", file_name);
+                } else {
+                    let mut lines = output.lines().collect::<Vec<_>>();
+                    let mut sharded_lines = sharded_output.lines().collect::<Vec<_>>();
+                    lines.sort_unstable();
+                    sharded_lines.sort_unstable();
+                    assert_eq!(lines, sharded_lines, "file: {}", file_name);
+                }
+            });
+            remove_dir_all(&output_dir).unwrap();
+            remove_dir_all(&sharded_output_dir).unwrap();
+        });
+
+        // A stopped sharded run keeps its shards' manifests, and resuming it writes the same files
+        let output_dir = std::env::temp_dir().join("pseudo_jaro_winkler_test_sharded_resume");
+        let resumed_output_dir = std::env::temp_dir().join("pseudo_jaro_winkler_test_sharded_resumed");
+        remove_dir_all(&output_dir).ok();
+        remove_dir_all(&resumed_output_dir).ok();
+        let options = MatchOptions { order: MatchOrder::ByCandidate, ..MatchOptions::new(0.8) };
+        pseudo_jaro_winkler_with_options(&query_names, &candidate_names, output_dir.clone(), &options);
+        // Without checkpointing, a stopped run only merges the names every shard finished, which are
+        // none until the last shard
+        [250, 450].iter().for_each(|&count| {
+            let mut stopped_options = MatchOptions { cancellation: CancellationToken::new(), ..options.clone() };
+            stopped_options.progress = Arc::new(CancelAfter { count, advanced: AtomicU64::new(0), cancellation: stopped_options.cancellation.clone() });
+            let stopped = pseudo_jaro_winkler_sharded_streaming(|| query_names.iter().cloned(), |range| candidate_names[range].to_vec(), candidate_names.len(), resumed_output_dir.clone(), &stopped_options, 3, 20);
+            assert!(stopped.is_stopped);
+            assert_eq!(stopped.completed.is_empty(), count < 400, "count: {}", count);
+            assert_eq!(read_dir(&resumed_output_dir).unwrap().count(), stopped.completed_count());
+            stopped.completed.iter().flat_map(std::ops::Range::clone).for_each(|a_id| {
+                let file_name = format!("{}.txt", a_id);
+                assert_eq!(read_to_string(output_dir.join(&file_name)).unwrap(), read_to_string(resumed_output_dir.join(&file_name)).unwrap(), "file: {}", file_name);
+            });
+            remove_dir_all(&resumed_output_dir).unwrap();
+        });
+
+        let mut stopped_options = MatchOptions { checkpoint: Checkpoint::Write, cancellation: CancellationToken::new(), ..options.clone() };
+        stopped_options.progress = Arc::new(CancelAfter { count: 250, advanced: AtomicU64::new(0), cancellation: stopped_options.cancellation.clone() });
+        let names_a = || query_names.iter().cloned();
+        let names_b = |range: std::ops::Range<usize>| candidate_names[range].to_vec();
+        let stopped = pseudo_jaro_winkler_sharded_streaming(names_a, names_b, candidate_names.len(), resumed_output_dir.clone(), &stopped_options, 3, 20);
+        assert_eq!(stopped, MatchOutcome { completed: vec![], is_stopped: true });
+        assert!(resumed_output_dir.join("shard_0").join(MANIFEST_FILE_NAME).exists());
+        assert!(resumed_output_dir.join("shard_1").join(MANIFEST_FILE_NAME).exists());
+        assert!(!resumed_output_dir.join("shard_2").exists());
+        assert_eq!(read_dir(&resumed_output_dir).unwrap().count(), 2);
+        let shard_1_completed = read_to_string(resumed_output_dir.join("shard_1").join(MANIFEST_FILE_NAME)).unwrap().lines().filter_map(|line| line.strip_prefix("completed ")).map(|range| {
+            let (start, end) = range.split_once("..").unwrap();
+            end.parse::<usize>().unwrap() - start.parse::<usize>().unwrap()
+        }).sum::<usize>();
+        assert!(shard_1_completed > 0 && shard_1_completed < query_names.len());
+        let progress = Arc::new(CountingProgress::default());
+        let resume_options = MatchOptions { checkpoint: Checkpoint::Resume, progress: progress.clone(), ..options };
+        let outcome = pseudo_jaro_winkler_sharded_streaming(names_a, names_b, candidate_names.len(), resumed_output_dir.clone(), &resume_options, 3, 20);
+        assert!(!outcome.is_stopped);
+        assert_eq!(*progress.totals.lock().unwrap(), vec![Some((2 * query_names.len() - shard_1_completed) as u64)]);
+        assert_eq!(progress.advanced.load(Ordering::Relaxed), (2 * query_names.len() - shard_1_completed) as u64);
+        assert_eq!(progress.finished.load(Ordering::Relaxed), 1);
+        assert_eq!(read_dir(&resumed_output_dir).unwrap().count(), query_names.len());
+        (0..query_names.len()).for_each(|a_id| {
+            let file_name = format!("{}.txt", a_id);
+            assert_eq!(read_to_string(output_dir.join(&file_name)).unwrap(), read_to_string(resumed_output_dir.join(&file_name)).unwrap(), "file: {}", file_name);
+        });
+        remove_dir_all(&output_dir).unwrap();
+        remove_dir_all(&resumed_output_dir).unwrap();
+    }
+
+    #[cfg(feature = "io")]
+    #[test]
+    fn test_saved_index() {
+        use std::{convert::TryInto, io::ErrorKind};
+        let query_names = read_names("./input/file_a_small.txt").into_iter().take(200).collect::<Vec<String>>();
+        let candidate_names = read_names("./input/file_b.txt");
+        let index_path = std::env::temp_dir().join("pseudo_jaro_winkler_test_saved_index.idx");
+        let output_dir = std::env::temp_dir().join("pseudo_jaro_winkler_test_saved_index");
+        let mapped_output_dir = std::env::temp_dir().join("pseudo_jaro_winkler_test_saved_index_mapped");
+        remove_dir_all(&output_dir).ok();
+        remove_dir_all(&mapped_output_dir).ok();
+        let candidate_index = CandidateIndex::new_shard(&candidate_names, 10);
+        candidate_index.save(&index_path).unwrap();
+        let mapped_index = CandidateIndex::open(&index_path).unwrap();
+        [ScoringStrategy::AllLetters, ScoringStrategy::RareLettersFirst].iter().for_each(|&strategy| {
+            [Kernel::Scalar, Kernel::detect()].iter().for_each(|&kernel| {
+                let options = MatchOptions { strategy, kernel, ..MatchOptions::new(0.8) };
+                pseudo_jaro_winkler_with_index(query_names.clone(), &candidate_index, output_dir.clone(), &options, 50);
+                pseudo_jaro_winkler_with_index(query_names.clone(), &mapped_index, mapped_output_dir.clone(), &options, 50);
+                (0..query_names.len()).for_each(|a_id| {
+                    let file_name = format!("{}.txt", a_id);
+                    assert_eq!(read_to_string(output_dir.join(&file_name)).unwrap(), read_to_string(mapped_output_dir.join(&file_name)).unwrap(), "file: {}", file_name);
+                });
+            });
+        });
+        assert!(CandidateIndex::open("./input/file_a_small.txt").is_err());
+        // Overwrites the start of a section of the index file, by its number in the header
+        let open_corrupted = |section: usize, bytes: &[u8]| {
+            let mut contents = std::fs::read(&index_path).unwrap();
+            let offset = u64::from_ne_bytes(contents[24 + section * 16..32 + section * 16].try_into().unwrap()) as usize;
+            contents[offset..offset + bytes.len()].copy_from_slice(bytes);
+            let corrupted_path = index_path.with_extension("corrupted.idx");
+            std::fs::write(&corrupted_path, contents).unwrap();
+            let opened = CandidateIndex::open(&corrupted_path).map(|_| ());
+            std::fs::remove_file(&corrupted_path).unwrap();
+            opened.map_err(|e| e.kind())
+        };
+        assert_eq!(open_corrupted(7, &[]), Ok(()));
+        assert_eq!(open_corrupted(7, &[0]), Err(ErrorKind::InvalidData));
+        assert_eq!(open_corrupted(7, &[17]), Err(ErrorKind::InvalidData));
+        assert_eq!(open_corrupted(4, &[0xff; 4]), Err(ErrorKind::InvalidData));
+        assert_eq!(open_corrupted(4, &[0, 0, 0, 0, 0, 0]), Err(ErrorKind::InvalidData));
+        assert_eq!(open_corrupted(9, &[16]), Err(ErrorKind::InvalidData));
+        remove_dir_all(&output_dir).unwrap();
+        remove_dir_all(&mapped_output_dir).unwrap();
+        std::fs::remove_file(&index_path).unwrap();
+    }
+
+    #[test]
+    fn test_empty_names() {
+        let candidate_names = ["anna", "", "bob", ""].iter().map(|name| name.to_string()).collect::<Vec<String>>();
+        let mut candidate_index = CandidateIndex::new(&candidate_names);
+        candidate_index.insert(4, "");
+        candidate_index.insert(5, "ann");
+        assert!(candidate_index.remove(3));
+        assert!(!candidate_index.remove(3));
+        assert!(std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| candidate_index.clone().insert(1, ""))).is_err());
+        let all_names = ["anna", "", "bob", "", "", "ann"];
+        let empty_b_ids = [1, 3, 4];
+        let sorted_matches = |candidate_index: &CandidateIndex, query_name: &str, options: &MatchOptions| {
+            let mut matches = candidate_index.matches(query_name, options).unwrap();
+            matches.sort_by_key(|&(b_id, _)| b_id);
+            matches
+        };
+
+        let skip = MatchOptions::new(0.0);
+        assert_eq!(skip.empty_names, EmptyNames::Skip);
+        assert!(sorted_matches(&candidate_index, "", &skip).is_empty());
+        assert!(sorted_matches(&candidate_index, "anna", &skip).iter().all(|(b_id, _)| !empty_b_ids.contains(b_id)));
+
+        let score = MatchOptions { empty_names: EmptyNames::Score, ..MatchOptions::new(0.0) };
+        let empty_matches = sorted_matches(&candidate_index, "", &score);
+        assert_eq!(empty_matches, vec![(0, 0.0), (1, 1.0), (2, 0.0), (4, 1.0), (5, 0.0)]);
+        empty_matches.iter().for_each(|&(b_id, jw)| assert_eq!(jw as f64, strsim::jaro_winkler("", all_names[b_id])));
+        let anna_empty_matches = sorted_matches(&candidate_index, "anna", &score).into_iter().filter(|(b_id, _)| empty_b_ids.contains(b_id)).collect::<Vec<_>>();
+        assert_eq!(anna_empty_matches, vec![(1, 0.0), (4, 0.0)]);
+        let score_high = MatchOptions { empty_names: EmptyNames::Score, ..MatchOptions::new(0.8) };
+        assert_eq!(sorted_matches(&candidate_index, "", &score_high), vec![(1, 1.0), (4, 1.0)]);
+        assert!(sorted_matches(&candidate_index, "anna", &score_high).iter().all(|(b_id, _)| !empty_b_ids.contains(b_id)));
+
+        #[cfg(feature = "io")]
+        {
+            let index_path = std::env::temp_dir().join("pseudo_jaro_winkler_test_empty_names.idx");
+            candidate_index.save(&index_path).unwrap();
+            let mapped_index = CandidateIndex::open(&index_path).unwrap();
+            assert_eq!(sorted_matches(&mapped_index, "", &score), empty_matches);
+            std::fs::remove_file(&index_path).unwrap();
+        }
+        candidate_index.compact();
+        assert_eq!(sorted_matches(&candidate_index, "", &score), empty_matches);
+
+        let error = MatchOptions { empty_names: EmptyNames::Error, ..MatchOptions::new(0.8) };
+        let non_empty_index = CandidateIndex::new(&["anna".to_string(), "bob".to_string()]);
+        assert_eq!(sorted_matches(&non_empty_index, "ann", &error).len(), 1);
+        assert_eq!(non_empty_index.matches("", &error), Err(EmptyNameError::Query));
+        assert_eq!(candidate_index.matches("ann", &error), Err(EmptyNameError::Candidate));
+        assert_eq!(candidate_index.matches("", &error), Err(EmptyNameError::Query));
+
+        #[cfg(feature = "io")]
+        {
+            let read = read_names_or_empty("anna\n\nBob\nbob".as_bytes()).map(|name| name.map_err(|e| e.to_string())).collect::<Vec<_>>();
+            assert_eq!(read[..2], [Ok("anna".to_string()), Ok("".to_string())]);
+            assert!(read[2].as_ref().unwrap_err().starts_with("line 3: \"Bob\""), "{:?}", read[2]);
+            assert_eq!(read[3], Ok("bob".to_string()));
+        }
+    }
+
+    #[test]
+    fn test_insert_remove() {
+        let query_names = read_names("./input/file_a_small.txt").into_iter().take(100).collect::<Vec<String>>();
+        let candidate_names = read_names("./input/file_b.txt").into_iter().take(20000).collect::<Vec<String>>();
+        let options = MatchOptions::new(0.8);
+        let sorted_matches = |candidate_index: &CandidateIndex, query_name: &str| {
+            let mut matches = candidate_index.matches(query_name, &options).unwrap();
+            matches.sort_by_key(|&(b_id, _)| b_id);
+            matches
+        };
+        let assert_same_matches = |candidate_index: &CandidateIndex, b_ids: &[usize]| {
+            let full_index = CandidateIndex::new(&candidate_names);
+            query_names.iter().for_each(|query_name| {
+                let expected = sorted_matches(&full_index, query_name).into_iter().filter(|(b_id, _)| b_ids.contains(b_id)).collect::<Vec<_>>();
+                assert_eq!(sorted_matches(candidate_index, query_name), expected, "query: {}", query_name);
+            });
+        };
+
+        let mut candidate_index = CandidateIndex::new(&candidate_names[..10000]);
+        (10000..19000).for_each(|b_id| candidate_index.insert(b_id, &candidate_names[b_id]));
+        (0..19000).step_by(7).for_each(|b_id| {
+            assert!(candidate_index.remove(b_id));
+            assert!(!candidate_index.remove(b_id));
+        });
+        assert!(!candidate_index.remove(19000));
+        assert!(std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| candidate_index.clone().insert(1, &candidate_names[1]))).is_err());
+        assert!(std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| candidate_index.clone().insert(10001, &candidate_names[10001]))).is_err());
+        let b_ids = (0..19000).filter(|b_id| b_id % 7 != 0).collect::<Vec<_>>();
+        assert!(candidate_index.needs_compaction());
+        assert_same_matches(&candidate_index, &b_ids);
+
+        #[cfg(feature = "std")]
+        let b_ids = {
+            let compaction = candidate_index.start_compaction();
+            (19000..20000).for_each(|b_id| candidate_index.insert(b_id, &candidate_names[b_id]));
+            (1..20000).step_by(7).for_each(|b_id| { candidate_index.remove(b_id); });
+            candidate_index.finish_compaction(compaction);
+            let b_ids = (0..20000).filter(|b_id| (*b_id >= 19000 || b_id % 7 != 0) && b_id % 7 != 1).collect::<Vec<_>>();
+            assert_same_matches(&candidate_index, &b_ids);
+            b_ids
+        };
+        candidate_index.compact();
+        assert!(!candidate_index.needs_compaction());
+        assert_same_matches(&candidate_index, &b_ids);
+    }
+
+    /// Sends an HTTP request to a server and returns the response's status code and body.
+    #[cfg(feature = "serve")]
+    fn http_request(address: std::net::SocketAddr, method: &str, path: &str, body: &str) -> (u16, String) {
+        use std::io::{Read, Write};
+        let mut stream = std::net::TcpStream::connect(address).unwrap();
+        write!(stream, "{} {} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\nContent-Length: {}\r\n\r\n{}", method, path, body.len(), body).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        let (head, body) = response.split_once("\r\n\r\n").unwrap();
+        (head[9..12].parse().unwrap(), body.to_string())
+    }
+
+    #[cfg(feature = "serve")]
+    #[test]
+    fn test_serve() {
+        let candidate_names = ["ellie", "nellie", "ellen", "john", "jon", "ellie"].iter().map(|name| name.to_string()).collect::<Vec<_>>();
+        let server = std::sync::Arc::new(MatchServer::bind(CandidateIndex::new(&candidate_names), "127.0.0.1:0", MatchOptions::new(0.8)).unwrap());
+        let running_server = server.clone();
+        let handle = std::thread::spawn(move || running_server.run());
+        let address = server.local_addr();
+
+        assert_eq!(http_request(address, "GET", "/health", ""), (200, r#"{"status":"ok"}"#.to_string()));
+        let (status, body) = http_request(address, "POST", "/match", r#"["ellie", "jon"]"#);
+        assert_eq!(status, 200);
+        let matches: Vec<Vec<serde_json::Value>> = serde_json::from_str(&body).unwrap();
+        assert_eq!(matches[0].iter().map(|m| m["id"].as_u64().unwrap()).collect::<Vec<_>>(), vec![0, 5, 1, 2]);
+        assert_eq!(matches[0][1]["name"], "ellie");
+        assert_eq!(matches[1].iter().map(|m| m["name"].as_str().unwrap()).collect::<Vec<_>>(), vec!["jon", "john"]);
+        let (status, body) = http_request(address, "POST", "/match", r#"{"names": ["ellie"], "top_k": 1, "min_jaro_winkler": 0.5}"#);
+        assert_eq!(status, 200);
+        assert_eq!(serde_json::from_str::<Vec<Vec<serde_json::Value>>>(&body).unwrap()[0].len(), 1);
+        assert_eq!(http_request(address, "POST", "/match", r#"["Ellie"]"#).0, 400);
+        assert_eq!(http_request(address, "POST", "/match", "not json").0, 400);
+        assert_eq!(http_request(address, "GET", "/missing", "").0, 404);
+        let names = vec!["ellie"; MAX_BODY_BYTES as usize / 10];
+        let body = serde_json::to_string(&names).unwrap();
+        let padded_body = |len: usize| body.clone() + &" ".repeat(len - body.len());
+        assert_eq!(http_request(address, "POST", "/match", &padded_body(MAX_BODY_BYTES as usize)).0, 200);
+        assert_eq!(http_request(address, "POST", "/match", &padded_body(MAX_BODY_BYTES as usize + 1)).0, 413);
+        let (status, metrics) = http_request(address, "GET", "/metrics", "");
+        assert_eq!(status, 200);
+        assert!(metrics.contains(&format!("pseudo_jaro_winkler_names_matched_total {}\n", 3 + names.len())), "{}", metrics);
+        assert!(metrics.contains("pseudo_jaro_winkler_failed_requests_total 4\n"), "{}", metrics);
+
+        server.stop();
+        handle.join().unwrap();
+    }
 }
@@ -5,47 +5,440 @@
 
 use pseudo_jaro_winkler::*;
 use std::{
-    fs::File,
+    fs::{File, remove_dir_all},
     path::PathBuf,
-    io::{BufRead, BufReader},
-    time::Instant
+    io::BufReader,
+    process,
+    sync::Arc,
+    time::{Duration, Instant}
 };
-use clap::{Arg, App};
+use clap::{Arg, App, AppSettings, ArgMatches, SubCommand};
+use itertools::Either;
 
 fn main() {
+    let top_k_arg = Arg::with_name("top_k")
+        .long("top-k")
+        .help("Only write the best matches for each name, at most this many, from best to worst.")
+        .takes_value(true);
+    let order_arg = Arg::with_name("order")
+        .long("order")
+        .help("Order of the matches in each output file: as they are scored, from best to worst score then by line number in file_b, or by line number in file_b. `unsorted` is the fastest.")
+        .takes_value(true)
+        .possible_values(&["unsorted", "score", "line"])
+        .default_value("unsorted");
     let cli_matches = App::new("pseudo_jaro_winkler")
         .version("0.1")
         .author("Jacob Wellington <jakew@umn.edu>")
         .about("Creates very fast jaro winkler scores between two datasets.")
+        .setting(AppSettings::SubcommandsNegateReqs)
         .arg(Arg::with_name("file_a")
             .help("First file to link. Must be a file where each row is a name.")
             .required(true)
             .index(1))
         .arg(Arg::with_name("file_b")
-            .help("Second file to link. Must be a file where each row is a name.")
+            .help("Second file to link. Must be a file where each row is a name, or an index file with --index.")
             .required(true)
             .index(2))
         .arg(Arg::with_name("output_dir")
             .help("Directory to put the output matches.")
             .required(true)
             .index(3))
+        .arg(Arg::with_name("strategy")
+            .long("strategy")
+            .help("Order in which to score the letters of each name. `rare-first` looks up the rarest letters first and skips names that can't reach the threshold.")
+            .takes_value(true)
+            .possible_values(&["all", "rare-first"])
+            .default_value("all"))
+        .arg(Arg::with_name("kernel")
+            .long("kernel")
+            .help("Instruction set used to score every letter. `auto` uses the fastest one supported by this CPU.")
+            .takes_value(true)
+            .possible_values(&["auto", "scalar", "sse4.1", "avx2"])
+            .default_value("auto"))
+        .arg(Arg::with_name("chunk_size")
+            .long("chunk-size")
+            .help("The number of names from file_a to match at a time, so that only one chunk of file_a is in memory. The manifest is written after each chunk, so a killed run only matches the chunk it was in again.")
+            .takes_value(true)
+            .default_value("10000"))
+        .arg(top_k_arg.clone())
+        .arg(order_arg.clone())
+        .arg(Arg::with_name("verify_margin")
+            .long("verify-margin")
+            .help("Rescore the names whose pseudo score is within this margin of the threshold with an exact jaro winkler, and only keep those whose exact score reaches it.")
+            .takes_value(true))
+        .arg(Arg::with_name("recall_safe")
+            .long("recall-safe")
+            .help("Never miss a pair whose exact jaro winkler reaches the threshold, by scoring against a lower threshold and rescoring every pair found with an exact jaro winkler. This is slower."))
+        .arg(Arg::with_name("empty_names")
+            .long("empty-names")
+            .help("What to do with blank lines in file_a and file_b, which are invalid names unless this is given: read them as empty names which never match, stop with the line number of the first one, or score them as strsim does.")
+            .takes_value(true)
+            .possible_values(&["skip", "error", "score"]))
+        .arg(Arg::with_name("progress")
+            .long("progress")
+            .help("How to report progress while matching: a progress bar, a log line every 10 seconds, or nothing.")
+            .takes_value(true)
+            .possible_values(&["bar", "log", "none"])
+            .default_value("bar"))
+        .arg(Arg::with_name("time_limit")
+            .long("time-limit")
+            .help("Stop matching after this many seconds, like pressing Ctrl-C. The names already matched are written in full, and the rest are left unmatched.")
+            .takes_value(true))
+        .arg(Arg::with_name("resume")
+            .long("resume")
+            .help("Resume a stopped run into the same output directory, skipping the names its manifest says are already written. The arguments must be the same as the stopped run's."))
+        .arg(Arg::with_name("shards")
+            .long("shards")
+            .help("Split file_b into this many shards which are indexed and matched one at a time, then merged into the output directory.")
+            .takes_value(true))
+        .arg(Arg::with_name("shard")
+            .long("shard")
+            .help("Only match this shard of file_b (starting at 0), writing it to the output directory to be merged later with the `merge` subcommand.")
+            .takes_value(true)
+            .requires("shards"))
+        .arg(Arg::with_name("index")
+            .long("index")
+            .help("Open file_b as an index file written by the `index` subcommand instead of indexing it.")
+            .conflicts_with("shards"))
+        .subcommand(SubCommand::with_name("merge")
+            .about("Merges the output directories of shards of file_b matched with `--shard`.")
+            .arg(Arg::with_name("output_dir")
+                .help("Directory to put the merged matches.")
+                .required(true)
+                .index(1))
+            .arg(Arg::with_name("shard_dirs")
+                .help("Output directories of every shard.")
+                .required(true)
+                .multiple(true)
+                .index(2))
+            .arg(top_k_arg.clone())
+            .arg(order_arg.clone()))
+        .subcommand(SubCommand::with_name("index")
+            .about("Indexes a file of names and writes the index to a file, which can be matched against with --index.")
+            .arg(Arg::with_name("file_b")
+                .help("File to index. Must be a file where each row is a name.")
+                .required(true)
+                .index(1))
+            .arg(Arg::with_name("index_file")
+                .help("File to write the index to.")
+                .required(true)
+                .index(2)))
+        .subcommand(SubCommand::with_name("evaluate")
+            .about("Scores a sample of file_a against file_b with both the pseudo and exact jaro winkler, and reports how far apart they are.")
+            .arg(Arg::with_name("file_a")
+                .help("File to sample the names to score from. Must be a file where each row is a name.")
+                .required(true)
+                .index(1))
+            .arg(Arg::with_name("file_b")
+                .help("File to score the sample against. Must be a file where each row is a name.")
+                .required(true)
+                .index(2))
+            .arg(Arg::with_name("sample")
+                .long("sample")
+                .help("Number of names of file_a to score, spread evenly through it. Every pair is scored exactly, so this takes around 15 seconds per 100 million pairs on each core.")
+                .takes_value(true)
+                .default_value("1000"))
+            .arg(Arg::with_name("threshold")
+                .long("threshold")
+                .help("Threshold to measure the precision and recall of the pseudo scores at.")
+                .takes_value(true)
+                .default_value("0.8"))
+            .arg(Arg::with_name("worst")
+                .long("worst")
+                .help("Number of pairs with the largest errors to report.")
+                .takes_value(true)
+                .default_value("10")))
+        .subcommand(SubCommand::with_name("bench")
+            .about("Times the pseudo jaro winkler with each kernel against other jaro winklers, matching file_a against file_b at 0.8 into a temporary directory.")
+            .arg(Arg::with_name("file_a")
+                .help("First file to link. Must be a file where each row is a name.")
+                .required(true)
+                .index(1))
+            .arg(Arg::with_name("file_b")
+                .help("Second file to link. Must be a file where each row is a name.")
+                .required(true)
+                .index(2))
+            .arg(Arg::with_name("algorithms")
+                .long("algorithms")
+                .help("Comma separated algorithms to time. Defaults to all of them, leaving out the kernels this CPU doesn't support.")
+                .takes_value(true)
+                .multiple(true)
+                .use_delimiter(true)
+                .possible_values(BENCH_ALGORITHMS))
+            .arg(Arg::with_name("threads")
+                .long("threads")
+                .help("Number of threads to match with.")
+                .takes_value(true)
+                .default_value("1"))
+            .arg(Arg::with_name("format")
+                .long("format")
+                .help("How to print the results.")
+                .takes_value(true)
+                .possible_values(&["table", "json"])
+                .default_value("table")))
+        .subcommand(SubCommand::with_name("serve")
+            .about("Answers requests to match names against file_b over HTTP. See the readme for the endpoints.")
+            .arg(Arg::with_name("file_b")
+                .help("File to match against. Must be a file where each row is a name, or an index file with --index.")
+                .required(true)
+                .index(1))
+            .arg(Arg::with_name("index")
+                .long("index")
+                .help("Open file_b as an index file written by the `index` subcommand instead of indexing it."))
+            .arg(Arg::with_name("address")
+                .long("address")
+                .help("Address to listen on.")
+                .takes_value(true)
+                .default_value("127.0.0.1:8080"))
+            .arg(Arg::with_name("min_jaro_winkler")
+                .long("min-jaro-winkler")
+                .help("Threshold for the matches of requests that don't set their own.")
+                .takes_value(true)
+                .default_value("0.8"))
+            .arg(top_k_arg.clone()))
         .get_matches();
+
+    if let Some(merge_matches) = cli_matches.subcommand_matches("merge") {
+        let output_dir = merge_matches.value_of("output_dir").unwrap();
+        let shard_dirs = merge_matches.values_of("shard_dirs").unwrap().map(PathBuf::from).collect::<Vec<_>>();
+        merge_shards(&shard_dirs, PathBuf::from(output_dir), parse_positive(merge_matches, "top_k"), parse_order(merge_matches));
+        return;
+    }
+    if let Some(index_matches) = cli_matches.subcommand_matches("index") {
+        let names_b = read_names(index_matches.value_of("file_b").unwrap(), "file_b", false).collect::<Vec<String>>();
+        let index_file = index_matches.value_of("index_file").unwrap();
+        CandidateIndex::new(&names_b).save(index_file).unwrap_or_else(|e| panic!("Error writing index file {}: {}", index_file, e));
+        return;
+    }
+    if let Some(evaluate_matches) = cli_matches.subcommand_matches("evaluate") {
+        let names_a = read_names(evaluate_matches.value_of("file_a").unwrap(), "file_a", false).collect::<Vec<String>>();
+        let names_b = read_names(evaluate_matches.value_of("file_b").unwrap(), "file_b", false).collect::<Vec<String>>();
+        let sample_size = parse_positive(evaluate_matches, "sample").unwrap().min(names_a.len());
+        let sample_a_ids = (0..sample_size).map(|i| i * names_a.len() / sample_size).collect::<Vec<usize>>();
+        let sample = sample_a_ids.iter().map(|&a_id| names_a[a_id].clone()).collect::<Vec<String>>();
+        let threshold = evaluate_matches.value_of("threshold").unwrap();
+        let min_jaro_winkler = threshold.parse::<f32>().ok().filter(|threshold| (0.0..=1.0).contains(threshold)).unwrap_or_else(|| panic!("Error: --threshold must be a number from 0 to 1: {}", threshold));
+        let worst_count = evaluate_matches.value_of("worst").unwrap();
+        let worst_count = worst_count.parse::<usize>().unwrap_or_else(|_| panic!("Error: --worst must be a number: {}", worst_count));
+        let mut evaluation = evaluate(&sample, &names_b, &EvaluateOptions { worst_count, ..EvaluateOptions::new(min_jaro_winkler) });
+        evaluation.worst_pairs.iter_mut().for_each(|pair| pair.a_id = sample_a_ids[pair.a_id]);
+        print!("{}", evaluation);
+        return;
+    }
+    if let Some(bench_matches) = cli_matches.subcommand_matches("bench") {
+        bench(bench_matches);
+        return;
+    }
+    if let Some(serve_matches) = cli_matches.subcommand_matches("serve") {
+        let file_b = serve_matches.value_of("file_b").unwrap();
+        let candidate_index = if serve_matches.is_present("index") {
+            CandidateIndex::open(file_b).unwrap_or_else(|e| panic!("Error opening index file {}: {}", file_b, e))
+        } else {
+            CandidateIndex::new(&read_names(file_b, "file_b", false).collect::<Vec<String>>())
+        };
+        let min_jaro_winkler = serve_matches.value_of("min_jaro_winkler").unwrap();
+        let min_jaro_winkler = min_jaro_winkler.parse::<f32>().ok().filter(|threshold| (0.0..=1.0).contains(threshold)).unwrap_or_else(|| panic!("Error: --min-jaro-winkler must be a number from 0 to 1: {}", min_jaro_winkler));
+        let options = MatchOptions { top_k: parse_positive(serve_matches, "top_k"), ..MatchOptions::new(min_jaro_winkler) };
+        let address = serve_matches.value_of("address").unwrap();
+        let server = MatchServer::bind(candidate_index, address, options).unwrap_or_else(|e| panic!("Error listening on {}: {}", address, e));
+        println!("Listening on http://{}", server.local_addr());
+        server.run();
+        return;
+    }
+
     let file_a : &str = cli_matches.value_of("file_a").unwrap();
     let file_b : &str = cli_matches.value_of("file_b").unwrap();
-    let names_a = BufReader::new(File::open(file_a).expect(&format!("Error opening file_a: {}", file_a))).lines().map(|n| n.unwrap()).collect::<Vec<String>>();
-    let names_b = BufReader::new(File::open(file_b).expect(&format!("Error opening file_b: {}", file_b))).lines().map(|n| n.unwrap()).collect::<Vec<String>>();
-    
-    names_a.iter().enumerate().for_each(|(i, name)| {
-        assert_ne!(name.len(), 0, "Error: file_a has blank line at line #: {}", i + 1);
-    });
-    names_b.iter().enumerate().for_each(|(i, name)| {
-        assert_ne!(name.len(), 0, "Error: file_b has blank line at line #: {}", i + 1);
+    let output_dir = PathBuf::from(cli_matches.value_of("output_dir").unwrap());
+    let chunk_size = parse_positive(&cli_matches, "chunk_size").unwrap();
+    let shard_count = parse_positive(&cli_matches, "shards").unwrap_or(1);
+    let only_shard = cli_matches.value_of("shard").map(|shard| {
+        shard.parse::<usize>().ok().filter(|&shard| shard < shard_count).unwrap_or_else(|| panic!("Error: --shard must be a number less than --shards: {}", shard))
     });
 
-    let output_dir = cli_matches.value_of("output_dir").unwrap();
     let start = Instant::now();
-    pseudo_jaro_winkler(&names_a, &names_b, PathBuf::from(output_dir), 0.8);
+    let strategy = match cli_matches.value_of("strategy").unwrap() {
+        "rare-first" => ScoringStrategy::RareLettersFirst,
+        _ => ScoringStrategy::AllLetters,
+    };
+    let kernel = match cli_matches.value_of("kernel").unwrap() {
+        "scalar" => Kernel::Scalar,
+        "sse4.1" => Kernel::Sse41,
+        "avx2" => Kernel::Avx2,
+        _ => Kernel::detect(),
+    };
+    assert!(kernel.is_available(), "Error: the {} kernel isn't supported by this CPU", cli_matches.value_of("kernel").unwrap());
+    let progress: Arc<dyn ProgressReporter> = match cli_matches.value_of("progress").unwrap() {
+        "log" => Arc::new(LogProgress::new(Duration::from_secs(10))),
+        "none" => Arc::new(NoProgress),
+        _ => Arc::new(BarProgress::new()),
+    };
+    let deadline = parse_positive(&cli_matches, "time_limit").map(|seconds| start + Duration::from_secs(seconds as u64));
+    let verify_margin = cli_matches.value_of("verify_margin").map(|margin| {
+        margin.parse::<f32>().ok().filter(|margin| (0.0..=1.0).contains(margin)).unwrap_or_else(|| panic!("Error: --verify-margin must be a number from 0 to 1: {}", margin))
+    });
+    let empty_names = cli_matches.value_of("empty_names").map(|empty_names| match empty_names {
+        "error" => EmptyNames::Error,
+        "score" => EmptyNames::Score,
+        _ => EmptyNames::Skip,
+    });
+    // Blank lines are invalid names, with their line number, unless they are to be skipped or scored
+    let allow_empty = matches!(empty_names, Some(EmptyNames::Skip | EmptyNames::Score));
+    let checkpoint = if cli_matches.is_present("resume") { Checkpoint::Resume } else { Checkpoint::Write };
+    let options = MatchOptions { strategy, kernel, top_k: parse_positive(&cli_matches, "top_k"), order: parse_order(&cli_matches), verify_margin, recall_safe: cli_matches.is_present("recall_safe"), empty_names: empty_names.unwrap_or_default(), progress, deadline, checkpoint, ..MatchOptions::new(0.8) };
+    // The first Ctrl-C or SIGTERM stops matching once the names being matched are written, and a
+    // second one exits straight away
+    let cancellation = options.cancellation.clone();
+    ctrlc::set_handler(move || {
+        if cancellation.is_cancelled() {
+            process::exit(130);
+        }
+        eprintln!("Stopping once the names being matched are written, press Ctrl-C again to exit now");
+        cancellation.cancel();
+    }).unwrap();
+
+    if cli_matches.is_present("index") {
+        let candidate_index = CandidateIndex::open(file_b).unwrap_or_else(|e| panic!("Error opening index file {}: {}", file_b, e));
+        let outcome = pseudo_jaro_winkler_with_index(read_names(file_a, "file_a", allow_empty), &candidate_index, output_dir, &options, chunk_size);
+        println!("{} ms", start.elapsed().as_millis());
+        exit_if_stopped(&outcome);
+        return;
+    }
+    let names_b_len = read_names(file_b, "file_b", allow_empty).count();
+    let names_b_shard = |range: std::ops::Range<usize>| read_names(file_b, "file_b", allow_empty).skip(range.start).take(range.len()).collect::<Vec<String>>();
+    let outcome = match only_shard {
+        None if shard_count > 1 => {
+            pseudo_jaro_winkler_sharded_streaming(|| read_names(file_a, "file_a", allow_empty), names_b_shard, names_b_len, output_dir, &options, shard_count, chunk_size)
+        },
+        _ => {
+            let range = shard_range(names_b_len, only_shard.unwrap_or(0), shard_count);
+            let candidate_index = CandidateIndex::new_shard(&names_b_shard(range.clone()), range.start);
+            pseudo_jaro_winkler_with_index(read_names(file_a, "file_a", allow_empty), &candidate_index, output_dir, &options, chunk_size)
+        },
+    };
     let elapsed = start.elapsed();
     println!("{} ms", elapsed.as_millis());
+    exit_if_stopped(&outcome);
+}
+
+/// The algorithms the `bench` subcommand can time.
+const BENCH_ALGORITHMS: &[&str] = &["pseudo", "pseudo-scalar", "pseudo-sse4.1", "pseudo-avx2", "pseudo-rare-first", "strsim", "eddie"];
+
+/// Times matching file_a against file_b with each algorithm on a thread pool of its own, and
+/// prints the wall time, comparisons per second and peak memory of each.
+fn bench(bench_matches: &ArgMatches) {
+    let names_a = read_names(bench_matches.value_of("file_a").unwrap(), "file_a", false).collect::<Vec<String>>();
+    let names_b = read_names(bench_matches.value_of("file_b").unwrap(), "file_b", false).collect::<Vec<String>>();
+    let threads = parse_positive(bench_matches, "threads").unwrap();
+    let pool = rayon::ThreadPoolBuilder::new().num_threads(threads).build().unwrap();
+    let output_dir = std::env::temp_dir().join(format!("pseudo_jaro_winkler_bench_{}", process::id()));
+    let algorithms = match bench_matches.values_of("algorithms") {
+        Some(algorithms) => algorithms.collect::<Vec<_>>(),
+        None => BENCH_ALGORITHMS.iter().copied().filter(|&algorithm| bench_kernel(algorithm).is_none_or(|kernel| kernel.is_available())).collect(),
+    };
+    let comparisons = names_a.len() as f64 * names_b.len() as f64;
+    let results = algorithms.iter().map(|&algorithm| {
+        remove_dir_all(&output_dir).ok();
+        reset_peak_memory();
+        let start = Instant::now();
+        pool.install(|| match algorithm {
+            "strsim" | "eddie" => bench_baseline(algorithm, &names_a, &names_b, output_dir.clone()),
+            _ => {
+                let kernel = bench_kernel(algorithm).unwrap_or_else(Kernel::detect);
+                assert!(kernel.is_available(), "Error: the {} kernel isn't supported by this CPU", algorithm);
+                let strategy = if algorithm == "pseudo-rare-first" { ScoringStrategy::RareLettersFirst } else { ScoringStrategy::AllLetters };
+                pseudo_jaro_winkler_with_options(&names_a, &names_b, output_dir.clone(), &MatchOptions { strategy, kernel, ..MatchOptions::new(0.8) });
+            },
+        });
+        let seconds = start.elapsed().as_secs_f64();
+        (algorithm, seconds, comparisons / seconds, peak_memory())
+    }).collect::<Vec<_>>();
+    remove_dir_all(&output_dir).ok();
+    if bench_matches.value_of("format") == Some("json") {
+        let results = results.iter().map(|&(algorithm, seconds, comparisons_per_second, peak_memory)| serde_json::json!({
+            "algorithm": algorithm,
+            "seconds": seconds,
+            "comparisons_per_second": comparisons_per_second,
+            "peak_memory_bytes": peak_memory,
+        })).collect::<Vec<_>>();
+        println!("{}", serde_json::json!({ "names_a": names_a.len(), "names_b": names_b.len(), "threads": threads, "results": results }));
+    } else {
+        println!("{} names against {} candidates on {} threads", names_a.len(), names_b.len(), threads);
+        println!("| algorithm         | wall time (seconds) | comparisons per second | peak memory (MB) | ratio to `pseudo` |");
+        println!("| ----------------- | ------------------- | ---------------------- | ---------------- | ----------------- |");
+        let pseudo_seconds = results.iter().find(|result| result.0 == "pseudo").map(|result| result.1);
+        for (algorithm, seconds, comparisons_per_second, peak_memory) in &results {
+            let peak_memory = peak_memory.map_or("unknown".to_string(), |bytes| format!("{:.1}", bytes as f64 / 1e6));
+            let ratio = pseudo_seconds.map_or("".to_string(), |pseudo_seconds| format!("{:.2}", seconds / pseudo_seconds));
+            println!("| {:<17} | {:>19.3} | {:>22.0} | {:>16} | {:>17} |", algorithm, seconds, comparisons_per_second, peak_memory, ratio);
+        }
+    }
+}
+
+/// The kernel timed by a `pseudo-{kernel}` algorithm of the `bench` subcommand.
+fn bench_kernel(algorithm: &str) -> Option<Kernel> {
+    match algorithm {
+        "pseudo-scalar" => Some(Kernel::Scalar),
+        "pseudo-sse4.1" => Some(Kernel::Sse41),
+        "pseudo-avx2" => Some(Kernel::Avx2),
+        _ => None,
+    }
+}
+
+#[cfg(feature = "baselines")]
+fn bench_baseline(algorithm: &str, names_a: &[String], names_b: &[String], output_dir: PathBuf) {
+    if algorithm == "strsim" {
+        strsim_jaro_winkler(names_a, names_b, output_dir, 0.8);
+    } else {
+        eddie_jaro_winkler(names_a, names_b, output_dir, 0.8);
+    }
 }
 
+#[cfg(not(feature = "baselines"))]
+fn bench_baseline(algorithm: &str, _names_a: &[String], _names_b: &[String], _output_dir: PathBuf) {
+    panic!("Error: {} needs the baselines feature", algorithm);
+}
+
+/// Resets the peak memory of the process to what it uses now, on Linux.
+fn reset_peak_memory() {
+    std::fs::write("/proc/self/clear_refs", "5").ok();
+}
+
+/// The most memory the process has had resident since the peak was last reset, on Linux.
+fn peak_memory() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let kilobytes = status.lines().find_map(|line| line.strip_prefix("VmHWM:"))?.trim().strip_suffix("kB")?.trim().parse::<u64>().ok()?;
+    Some(kilobytes * 1024)
+}
+
+/// Exits with an error if matching was stopped before every name was matched.
+fn exit_if_stopped(outcome: &MatchOutcome) {
+    if outcome.is_stopped {
+        eprintln!("Stopped after matching {} names, pass --resume to carry on", outcome.completed_count());
+        process::exit(1);
+    }
+}
+
+/// Reads the names in a file, one per line, which must be valid, or blank when `allow_empty`. See
+/// `is_valid_name`.
+fn read_names(path: &str, file_label: &str, allow_empty: bool) -> impl Iterator<Item = String> {
+    let file_label = file_label.to_string();
+    let file = File::open(path).unwrap_or_else(|_| panic!("Error opening {}: {}", file_label, path));
+    let names = if allow_empty { Either::Left(pseudo_jaro_winkler::read_names_or_empty(BufReader::new(file))) } else { Either::Right(pseudo_jaro_winkler::read_names(BufReader::new(file))) };
+    names.map(move |name| name.unwrap_or_else(|e| panic!("Error: {} has an invalid name at {}", file_label, e)))
+}
+
+/// Parses the order to write the matches of each name in.
+fn parse_order(cli_matches: &ArgMatches) -> MatchOrder {
+    match cli_matches.value_of("order").unwrap() {
+        "score" => MatchOrder::ByScore,
+        "line" => MatchOrder::ByCandidate,
+        _ => MatchOrder::Unsorted,
+    }
+}
+
+/// Parses an optional argument which must be a positive number.
+fn parse_positive(cli_matches: &ArgMatches, name: &str) -> Option<usize> {
+    cli_matches.value_of(name).map(|value| {
+        value.parse::<usize>().ok().filter(|&value| value > 0).unwrap_or_else(|| panic!("Error: --{} must be a positive number: {}", name.replace('_', "-"), value))
+    })
+}
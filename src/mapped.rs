@@ -0,0 +1,177 @@
+// This file is part of the IPUMS's pseudo_jaro_winkler.
+// For copyright and licensing information, see the NOTICE and LICENSE files
+// in this project's top-level directory, and also on-line at:
+//   https://github.com/ipums/pseudo_jaro_winkler
+
+//! The on-disk format of a candidate index, which is memory-mapped and queried in place instead of
+//! being read into memory. Processes opening the same index file share the operating system's
+//! cached copy of it.
+//!
+//! Every array of a `CandidateIndex` is stored as is, in native byte order, one after another:
+//!
+//! * The magic bytes `PJWINDEX`, then the format version and `BYTE_ORDER_CHECK` as `u64`s.
+//! * The byte offset and byte length of each of the `SECTION_COUNT` arrays, as `u64`s.
+//! * The arrays, each starting at a multiple of `SECTION_ALIGN` bytes.
+
+use std::convert::TryInto;
+use std::fs::File;
+use std::io::{self, BufWriter, Error, ErrorKind};
+use std::io::prelude::*;
+use std::mem::{align_of, size_of};
+use std::path::Path;
+use std::sync::Arc;
+use memmap2::Mmap;
+use crate::CandidateIndex;
+use crate::array::{Array, Pod};
+use crate::kernel::DenseCandidates;
+use crate::update::Updates;
+
+const MAGIC: &[u8; 8] = b"PJWINDEX";
+const VERSION: u64 = 3;
+/// Written in native byte order, so that an index from a machine with a different byte order is
+/// rejected.
+const BYTE_ORDER_CHECK: u64 = 0x0102_0304_0506_0708;
+const SECTION_COUNT: usize = 12;
+const SECTION_ALIGN: usize = 64;
+const HEADER_LEN: usize = 24 + SECTION_COUNT * 16;
+
+/// Views a section of the mapped index file as an array.
+fn mapped<T: Pod>(mmap: &Arc<Mmap>, (offset, byte_len): (usize, usize)) -> io::Result<Array<T>> {
+    let in_bounds = offset.checked_add(byte_len).is_some_and(|end| end <= mmap.len());
+    let ptr = mmap.as_ptr().wrapping_add(offset);
+    if !in_bounds || !byte_len.is_multiple_of(size_of::<T>()) || !(ptr as usize).is_multiple_of(align_of::<T>()) {
+        return Err(invalid_data("a section is out of bounds or misaligned"));
+    }
+    Ok(unsafe { Array::from_raw_parts(ptr as *const T, byte_len / size_of::<T>(), mmap.clone()) })
+}
+
+fn invalid_data(message: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, format!("Invalid candidate index file: {}", message))
+}
+
+impl CandidateIndex {
+    /// Writes the index to a file which can be opened with `CandidateIndex::open`. Names that have
+    /// been inserted or removed since the index was last compacted are written as if it had been
+    /// compacted.
+    ///
+    /// # Arguments
+    ///
+    /// * `path`: The location of the index file to write.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        if !self.updates.is_empty() {
+            return self.compacted().save(path);
+        }
+        let sections: [&[u8]; SECTION_COUNT] = [
+            self.name_bytes.as_bytes(),
+            self.name_offsets.as_bytes(),
+            self.b_ids.as_bytes(),
+            self.b_id_offsets.as_bytes(),
+            self.candidate_lookup.as_bytes(),
+            self.len_offsets.as_bytes(),
+            self.padded_letters.as_bytes(),
+            self.name_lens.as_bytes(),
+            self.dense_candidates.letter_masks.as_bytes(),
+            self.dense_candidates.len_indices.as_bytes(),
+            self.dense_candidates.len_partials.as_bytes(),
+            self.empty_b_ids.as_bytes(),
+        ];
+        let mut file = BufWriter::new(File::create(path)?);
+        file.write_all(MAGIC)?;
+        file.write_all(&VERSION.to_ne_bytes())?;
+        file.write_all(&BYTE_ORDER_CHECK.to_ne_bytes())?;
+        let mut offset = HEADER_LEN;
+        let offsets = sections.iter().map(|section| {
+            offset = offset.next_multiple_of(SECTION_ALIGN);
+            let section_offset = offset;
+            offset += section.len();
+            section_offset
+        }).collect::<Vec<_>>();
+        for (section, &section_offset) in sections.iter().zip(&offsets) {
+            file.write_all(&(section_offset as u64).to_ne_bytes())?;
+            file.write_all(&(section.len() as u64).to_ne_bytes())?;
+        }
+        let mut written = HEADER_LEN;
+        for (section, &section_offset) in sections.iter().zip(&offsets) {
+            file.write_all(&vec![0; section_offset - written])?;
+            file.write_all(section)?;
+            written = section_offset + section.len();
+        }
+        file.flush()
+    }
+
+    /// Opens an index file written by `CandidateIndex::save` without reading it into memory. The
+    /// file is memory-mapped and queried in place, so it must not be modified while it is open.
+    ///
+    /// # Arguments
+    ///
+    /// * `path`: The location of the index file to open.
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<CandidateIndex> {
+        let file = File::open(path)?;
+        let mmap = Arc::new(unsafe { Mmap::map(&file)? });
+        if mmap.len() < HEADER_LEN || &mmap[..8] != MAGIC {
+            return Err(invalid_data("it doesn't start with the expected header"));
+        }
+        let read_u64 = |offset: usize| u64::from_ne_bytes(mmap[offset..offset + 8].try_into().unwrap());
+        if read_u64(8) != VERSION {
+            return Err(invalid_data("it was written by an unsupported version"));
+        }
+        if read_u64(16) != BYTE_ORDER_CHECK {
+            return Err(invalid_data("it was written on a machine with a different byte order"));
+        }
+        let section = |i: usize| (read_u64(24 + i * 16) as usize, read_u64(32 + i * 16) as usize);
+        let name_lens: Array<u8> = mapped(&mmap, section(7))?;
+        let candidate_index = CandidateIndex {
+            name_bytes: mapped(&mmap, section(0))?,
+            name_offsets: mapped(&mmap, section(1))?,
+            b_ids: mapped(&mmap, section(2))?,
+            b_id_offsets: mapped(&mmap, section(3))?,
+            candidate_lookup: mapped(&mmap, section(4))?,
+            len_offsets: mapped(&mmap, section(5))?,
+            padded_letters: mapped(&mmap, section(6))?,
+            dense_candidates: DenseCandidates {
+                len: name_lens.len(),
+                letter_masks: mapped(&mmap, section(8))?,
+                len_indices: mapped(&mmap, section(9))?,
+                len_partials: mapped(&mmap, section(10))?,
+            },
+            name_lens,
+            empty_b_ids: mapped(&mmap, section(11))?,
+            updates: Updates::default(),
+        };
+        if !candidate_index.has_consistent_lengths() {
+            return Err(invalid_data("its sections don't fit together"));
+        }
+        if !candidate_index.has_valid_contents() {
+            return Err(invalid_data("its name lengths or candidate lookup are out of range"));
+        }
+        Ok(candidate_index)
+    }
+
+    /// Checks that the arrays have the lengths expected for the number of unique names, and that
+    /// the offsets stay within the arrays they point into. The scoring relies on this.
+    fn has_consistent_lengths(&self) -> bool {
+        let len = self.name_lens.len();
+        let padded_len = self.dense_candidates.len_indices.len();
+        let offsets_fit = |offsets: &[u64], array_len: usize| offsets.windows(2).all(|pair| pair[0] <= pair[1]) && offsets.last().is_some_and(|&end| end as usize <= array_len);
+        self.name_offsets.len() == len + 1
+            && offsets_fit(&self.name_offsets, self.name_bytes.len())
+            && self.b_id_offsets.len() == len + 1
+            && offsets_fit(&self.b_id_offsets, self.b_ids.len())
+            && self.len_offsets.len() == 27
+            && offsets_fit(&self.len_offsets.iter().flatten().copied().collect::<Vec<_>>(), self.candidate_lookup.len())
+            && self.padded_letters.len() == len
+            && padded_len >= len && padded_len.is_multiple_of(crate::kernel::LANES)
+            && self.dense_candidates.letter_masks.len() == 27 * padded_len
+            && self.dense_candidates.len_partials.len() == padded_len
+    }
+
+    /// Checks that the values the scoring indexes by are in range: every unique name is 1 to 16
+    /// letters long, and every entry of the candidate lookup is of a unique name, with its length.
+    /// Must only be called once the lengths are known to be consistent.
+    fn has_valid_contents(&self) -> bool {
+        let len = self.name_lens.len();
+        self.name_lens.iter().all(|name_len| (1..=16).contains(name_len))
+            && self.candidate_lookup.iter().all(|c_info| (c_info.name_index as usize) < len && c_info.len == self.name_lens[c_info.name_index as usize] as u16)
+            && self.dense_candidates.len_indices.iter().all(|&len_index| len_index < 16)
+    }
+}
@@ -0,0 +1,153 @@
+// This file is part of the IPUMS's pseudo_jaro_winkler.
+// For copyright and licensing information, see the NOTICE and LICENSE files
+// in this project's top-level directory, and also on-line at:
+//   https://github.com/ipums/pseudo_jaro_winkler
+
+//! Reporting the progress of matching files of names. Nothing is reported unless a reporter is set
+//! in `MatchOptions::progress`, so the library stays silent inside batch jobs and services.
+
+use core::fmt;
+#[cfg(feature = "std")]
+use std::sync::atomic::{AtomicU64, Ordering};
+#[cfg(feature = "std")]
+use std::sync::Mutex;
+#[cfg(feature = "std")]
+use std::time::{Duration, Instant};
+
+/// Receives the progress of matching a list of names, for showing it to the user.
+pub trait ProgressReporter: Send + Sync {
+    /// Called when matching starts, with the number of names to match if it is known.
+    fn start(&self, total: Option<u64>);
+    /// Called as names are matched with the number just matched, from many threads at once.
+    fn advance(&self, count: u64);
+    /// Called once every name has been matched.
+    fn finish(&self);
+}
+
+impl fmt::Debug for dyn ProgressReporter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("ProgressReporter")
+    }
+}
+
+/// Doesn't report anything. This is the default.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoProgress;
+
+impl ProgressReporter for NoProgress {
+    fn start(&self, _total: Option<u64>) {}
+    fn advance(&self, _count: u64) {}
+    fn finish(&self) {}
+}
+
+/// Draws a progress bar on the terminal with indicatif, or a spinner if the number of names isn't
+/// known.
+#[cfg(feature = "progress")]
+#[derive(Debug)]
+pub struct BarProgress {
+    bar: std::sync::RwLock<indicatif::ProgressBar>,
+}
+
+#[cfg(feature = "progress")]
+impl BarProgress {
+    pub fn new() -> BarProgress {
+        BarProgress { bar: std::sync::RwLock::new(indicatif::ProgressBar::hidden()) }
+    }
+}
+
+#[cfg(feature = "progress")]
+impl Default for BarProgress {
+    fn default() -> BarProgress {
+        BarProgress::new()
+    }
+}
+
+#[cfg(feature = "progress")]
+impl ProgressReporter for BarProgress {
+    fn start(&self, total: Option<u64>) {
+        *self.bar.write().unwrap() = match total {
+            Some(total) => indicatif::ProgressBar::new(total),
+            None => indicatif::ProgressBar::new_spinner(),
+        };
+    }
+
+    fn advance(&self, count: u64) {
+        self.bar.read().unwrap().inc(count);
+    }
+
+    fn finish(&self) {
+        self.bar.read().unwrap().finish();
+    }
+}
+
+/// Writes a line with the number of names matched, the names matched per second and the estimated
+/// time left at most once every `interval`, and a last line once every name has been matched. The
+/// lines are written to stderr unless another sink is given, such as a logging library.
+#[cfg(feature = "std")]
+pub struct LogProgress {
+    interval: Duration,
+    sink: Box<dyn Fn(&str) + Send + Sync>,
+    matched: AtomicU64,
+    /// When matching started, the number of names to match, and when a line was last written.
+    state: Mutex<(Instant, Option<u64>, Instant)>,
+}
+
+#[cfg(feature = "std")]
+impl LogProgress {
+    /// Writes the lines to stderr.
+    pub fn new(interval: Duration) -> LogProgress {
+        LogProgress::with_sink(interval, |line| eprintln!("{}", line))
+    }
+
+    /// Passes each line to `sink` instead of writing it to stderr.
+    pub fn with_sink<F: Fn(&str) + Send + Sync + 'static>(interval: Duration, sink: F) -> LogProgress {
+        let now = Instant::now();
+        LogProgress { interval, sink: Box::new(sink), matched: AtomicU64::new(0), state: Mutex::new((now, None, now)) }
+    }
+
+    fn line(&self, matched: u64, started: Instant, total: Option<u64>, is_finished: bool) -> String {
+        let elapsed = started.elapsed().as_secs_f64();
+        let rate = if elapsed > 0.0 { matched as f64 / elapsed } else { 0.0 };
+        match total {
+            _ if is_finished => format!("Matched {} names in {:.1}s ({:.0} names/s)", matched, elapsed, rate),
+            Some(total) => {
+                let eta = if rate > 0.0 { format!("{:.0}s", total.saturating_sub(matched) as f64 / rate) } else { "unknown".to_string() };
+                format!("Matched {}/{} names ({:.1}%), {:.0} names/s, ETA {}", matched, total, 100.0 * matched as f64 / total.max(1) as f64, rate, eta)
+            },
+            None => format!("Matched {} names, {:.0} names/s", matched, rate),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl fmt::Debug for LogProgress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LogProgress").field("interval", &self.interval).field("matched", &self.matched).finish()
+    }
+}
+
+#[cfg(feature = "std")]
+impl ProgressReporter for LogProgress {
+    fn start(&self, total: Option<u64>) {
+        let now = Instant::now();
+        self.matched.store(0, Ordering::Relaxed);
+        *self.state.lock().unwrap() = (now, total, now);
+    }
+
+    fn advance(&self, count: u64) {
+        let matched = self.matched.fetch_add(count, Ordering::Relaxed) + count;
+        // Threads that find another one already checking the time just move on
+        if let Ok(mut state) = self.state.try_lock() {
+            let (started, total, last_logged) = *state;
+            if last_logged.elapsed() >= self.interval {
+                state.2 = Instant::now();
+                (self.sink)(&self.line(matched, started, total, false));
+            }
+        }
+    }
+
+    fn finish(&self) {
+        let (started, total, _) = *self.state.lock().unwrap();
+        (self.sink)(&self.line(self.matched.load(Ordering::Relaxed), started, total, true));
+    }
+}
@@ -0,0 +1,84 @@
+// This file is part of the IPUMS's pseudo_jaro_winkler.
+// For copyright and licensing information, see the NOTICE and LICENSE files
+// in this project's top-level directory, and also on-line at:
+//   https://github.com/ipums/pseudo_jaro_winkler
+
+//! How far a pseudo score can fall below the exact jaro winkler, for matching without ever missing
+//! a candidate whose exact score reaches the threshold. See `MatchOptions::recall_safe`.
+//!
+//! The pseudo score of a query and a candidate is built from the same parts as the exact one (see
+//! `jaro_winkler`), and differs from it in only a few ways:
+//!
+//! * The matches are the same. Both use a matching distance of half the longer name less one, and
+//!   match each letter of the query to the first unmatched equal letter of the candidate.
+//! * The common prefix is the same, since the letters of the prefix always match their own
+//!   position, but the pseudo score only counts up to 4 letters of it.
+//! * A match is counted as a transposition when it is before any earlier match, not just the one
+//!   before it, so there are at least as many transpositions. One is taken off when there are more
+//!   than half as many transpositions as matches. When the exact score has no transpositions the
+//!   matches are in order, so the pseudo score has none either. Neither the first match nor the
+//!   matches of the common prefix are ever transpositions.
+//! * The fractions of each name that match are rounded down to 1024ths.
+//!
+//! So the lowest pseudo score a candidate can have, given that its exact score reaches the
+//! threshold, is found by going through every number of matches and length of common prefix, with
+//! either no transpositions, or a single exact transposition and as many pseudo transpositions as
+//! there can be. `max_deviation` is the most the pseudo score can fall below the exact one this
+//! way, which is around 0.27 for long names with a transposition. Names found in practice fall less
+//! below it, around 0.21 at most in the `input/` name files.
+
+use crate::CandidateScore;
+
+/// The pseudo score and the exact jaro winkler of a query and candidate with the given parts.
+/// Returns `None` if they can't have those parts.
+///
+/// # Arguments
+///
+/// * `query_len`, `candidate_len`: The lengths of the names.
+/// * `matches`: The number of matching letters.
+/// * `prefix_len`: The length of the common prefix.
+/// * `is_transposed`: Whether the exact score has a transposition, in which case the pseudo score
+///   has as many as it can.
+fn scores(query_len: usize, candidate_len: usize, matches: usize, prefix_len: usize, is_transposed: bool) -> Option<(f32, f64)> {
+    let pseudo_transpositions = if is_transposed { (matches - prefix_len).checked_sub(1).filter(|&count| count >= 1)? } else { 0 };
+    let exact_transpositions = is_transposed as usize;
+    let mut score = CandidateScore::new(candidate_len as u8);
+    score.matches = matches as u8;
+    score.transposition_count = pseudo_transpositions as u8;
+    score.used_exact = (1 << prefix_len.min(4)) - 1;
+    let pseudo = score.calculate_jaro_winkler(((1.0 / query_len as f32) * 1024.0) as u16);
+    let matches = matches as f64;
+    let jaro = (1.0 / 3.0) * (matches / query_len as f64 + matches / candidate_len as f64 + (matches - exact_transpositions as f64) / matches);
+    Some((pseudo, (jaro + 0.1 * prefix_len as f64 * (1.0 - jaro)).min(1.0)))
+}
+
+/// Every possible pseudo score and exact jaro winkler of a query and a candidate of these lengths
+/// which have at least one match.
+fn possible_scores(query_len: usize, candidate_len: usize) -> impl Iterator<Item = (f32, f64)> {
+    (1..=query_len.min(candidate_len)).flat_map(move |matches| {
+        (0..=matches).flat_map(move |prefix_len| {
+            [false, true].iter().filter_map(move |&is_transposed| scores(query_len, candidate_len, matches, prefix_len, is_transposed))
+        })
+    })
+}
+
+/// The lowest pseudo score a candidate can have when its exact jaro winkler with a query of length
+/// `query_len` reaches `min_jaro_winkler`. Scoring with this as the threshold finds every candidate
+/// whose exact score reaches `min_jaro_winkler`, along with others which don't.
+pub fn recall_safe_threshold(query_len: usize, min_jaro_winkler: f32) -> f32 {
+    if min_jaro_winkler <= 0.0 {
+        return min_jaro_winkler;
+    }
+    (1..=16).flat_map(|candidate_len| possible_scores(query_len, candidate_len))
+        .filter(|&(_, exact)| exact as f32 >= min_jaro_winkler)
+        .map(|(pseudo, _)| pseudo)
+        .fold(f32::INFINITY, f32::min)
+        .min(min_jaro_winkler)
+}
+
+/// The most that the pseudo score of any two valid names can fall below their exact jaro winkler.
+pub fn max_deviation() -> f64 {
+    (1..=16).flat_map(|query_len| (1..=16).flat_map(move |candidate_len| possible_scores(query_len, candidate_len)))
+        .map(|(pseudo, exact)| exact - pseudo as f64)
+        .fold(0.0, f64::max)
+}
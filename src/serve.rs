@@ -0,0 +1,180 @@
+// This file is part of the IPUMS's pseudo_jaro_winkler.
+// For copyright and licensing information, see the NOTICE and LICENSE files
+// in this project's top-level directory, and also on-line at:
+//   https://github.com/ipums/pseudo_jaro_winkler
+
+//! A local HTTP service that matches names against a candidate index, for looking up similar names
+//! interactively. Requests are handled in parallel on the rayon thread pool.
+//!
+//! * `POST /match` takes a JSON list of names, or an object such as
+//!   `{"names": ["jon"], "min_jaro_winkler": 0.9, "top_k": 5}`, and responds with a list of the
+//!   matches of each name from best to worst, such as
+//!   `[[{"id": 12, "name": "john", "score": 0.93}]]`.
+//!   Request bodies larger than `MAX_BODY_BYTES` are answered with a 413.
+//! * `GET /health` responds with `{"status": "ok"}` once the index is loaded.
+//! * `GET /metrics` responds with counts of the requests handled, in the Prometheus text format.
+
+use std::collections::HashMap;
+use std::io::{self, Read};
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::Instant;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use tiny_http::{Header, Method, Request, Response, Server};
+use crate::{is_valid_name, keep_top_k, CandidateIndex, EmptyNameError, MatchOptions, VALID_NAME_DESCRIPTION};
+
+/// The largest `POST /match` request body that is read, in bytes.
+pub const MAX_BODY_BYTES: u64 = 1 << 20;
+
+/// The body of a `POST /match` request.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum MatchRequest {
+    Names(Vec<String>),
+    WithOptions {
+        names: Vec<String>,
+        min_jaro_winkler: Option<f32>,
+        top_k: Option<usize>,
+    },
+}
+
+/// A single match in the response to a `POST /match` request.
+#[derive(Serialize)]
+struct Match<'a> {
+    /// The index of the candidate in names_b.
+    id: usize,
+    name: &'a str,
+    score: f32,
+}
+
+/// Counts of the requests handled, reported by `GET /metrics`.
+#[derive(Default)]
+struct Metrics {
+    requests: AtomicU64,
+    failed_requests: AtomicU64,
+    names_matched: AtomicU64,
+    matches_found: AtomicU64,
+    match_microseconds: AtomicU64,
+}
+
+/// A server answering requests to match names against a candidate index. See the module
+/// documentation for the endpoints.
+pub struct MatchServer {
+    server: Server,
+    candidate_index: CandidateIndex,
+    /// The options used for requests that don't set their own threshold or top-k.
+    options: MatchOptions,
+    metrics: Metrics,
+    stopped: AtomicBool,
+}
+
+impl MatchServer {
+    /// Starts listening for requests. They aren't answered until `MatchServer::run` is called.
+    ///
+    /// # Arguments
+    ///
+    /// * `candidate_index`: The candidates to match against.
+    /// * `address`: The address to listen on, such as `127.0.0.1:8080`. Use port 0 to listen on any
+    ///   free port.
+    /// * `options`: How to score the matches, and the threshold and top-k used by default.
+    pub fn bind(candidate_index: CandidateIndex, address: &str, options: MatchOptions) -> io::Result<MatchServer> {
+        let server = Server::http(address).map_err(io::Error::other)?;
+        Ok(MatchServer { server, candidate_index, options, metrics: Metrics::default(), stopped: AtomicBool::new(false) })
+    }
+
+    /// The address the server is listening on.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.server.server_addr().to_ip().expect("the server listens on an IP address")
+    }
+
+    /// Answers requests until `MatchServer::stop` is called.
+    pub fn run(&self) {
+        rayon::in_place_scope(|scope| {
+            while !self.stopped.load(Ordering::SeqCst) {
+                match self.server.recv() {
+                    Ok(request) => scope.spawn(move |_| self.respond(request)),
+                    Err(e) if !self.stopped.load(Ordering::SeqCst) => eprintln!("Error receiving request: {}", e),
+                    Err(_) => (),
+                }
+            }
+        });
+    }
+
+    /// Makes `MatchServer::run` return once the requests it already received are answered.
+    pub fn stop(&self) {
+        self.stopped.store(true, Ordering::SeqCst);
+        self.server.unblock();
+    }
+
+    fn respond(&self, mut request: Request) {
+        self.metrics.requests.fetch_add(1, Ordering::Relaxed);
+        let (status, content_type, body) = match (request.method(), request.url()) {
+            (Method::Get, "/health") => (200, "application/json", r#"{"status":"ok"}"#.to_string()),
+            (Method::Get, "/metrics") => (200, "text/plain; version=0.0.4", self.metrics_text()),
+            (Method::Post, "/match") => {
+                let mut body = String::new();
+                // Reading one byte past the limit tells a body at the limit from a larger one
+                match request.as_reader().take(MAX_BODY_BYTES + 1).read_to_string(&mut body) {
+                    Ok(_) if body.len() as u64 > MAX_BODY_BYTES => {
+                        (413, "application/json", serde_json::json!({ "error": format!("request body larger than {} bytes", MAX_BODY_BYTES) }).to_string())
+                    },
+                    Ok(_) => match self.match_body(&body) {
+                        Ok(matches) => (200, "application/json", matches),
+                        Err(error) => (400, "application/json", serde_json::json!({ "error": error }).to_string()),
+                    },
+                    Err(e) => (400, "application/json", serde_json::json!({ "error": e.to_string() }).to_string()),
+                }
+            },
+            _ => (404, "application/json", r#"{"error":"not found"}"#.to_string()),
+        };
+        if status != 200 {
+            self.metrics.failed_requests.fetch_add(1, Ordering::Relaxed);
+        }
+        let header = Header::from_bytes("Content-Type", content_type).unwrap();
+        let response = Response::from_string(body).with_status_code(status).with_header(header);
+        if let Err(e) = request.respond(response) {
+            eprintln!("Error sending response: {}", e);
+        }
+    }
+
+    /// Matches the names in the body of a `POST /match` request, returning the response body.
+    fn match_body(&self, body: &str) -> Result<String, String> {
+        let mut options = self.options.clone();
+        let names = match serde_json::from_str(body).map_err(|e| format!("invalid request: {}", e))? {
+            MatchRequest::Names(names) => names,
+            MatchRequest::WithOptions { names, min_jaro_winkler, top_k } => {
+                options.min_jaro_winkler = min_jaro_winkler.unwrap_or(options.min_jaro_winkler);
+                options.top_k = top_k.or(options.top_k);
+                names
+            },
+        };
+        if let Some(name) = names.iter().find(|name| !is_valid_name(name)) {
+            return Err(format!("invalid name {:?}: {}", name, VALID_NAME_DESCRIPTION));
+        }
+        let start = Instant::now();
+        let matches = names.par_iter().map(|name| {
+            let named_matches = self.candidate_index.named_matches(name, &options)?;
+            let names_by_id = named_matches.iter().map(|&(b_id, name, _)| (b_id, name)).collect::<HashMap<_, _>>();
+            let mut matches = named_matches.into_iter().map(|(b_id, _, jw)| (b_id, jw)).collect();
+            keep_top_k(&mut matches, options.top_k.unwrap_or(usize::MAX));
+            Ok(matches.into_iter().map(|(id, score)| Match { id, name: names_by_id[&id], score }).collect::<Vec<_>>())
+        }).collect::<Result<Vec<_>, EmptyNameError>>().map_err(|e| e.to_string())?;
+        self.metrics.match_microseconds.fetch_add(start.elapsed().as_micros() as u64, Ordering::Relaxed);
+        self.metrics.names_matched.fetch_add(names.len() as u64, Ordering::Relaxed);
+        self.metrics.matches_found.fetch_add(matches.iter().map(Vec::len).sum::<usize>() as u64, Ordering::Relaxed);
+        Ok(serde_json::to_string(&matches).unwrap())
+    }
+
+    fn metrics_text(&self) -> String {
+        [
+            ("requests_total", "Requests received.", &self.metrics.requests),
+            ("failed_requests_total", "Requests answered with an error.", &self.metrics.failed_requests),
+            ("names_matched_total", "Names matched by POST /match requests.", &self.metrics.names_matched),
+            ("matches_found_total", "Matches returned by POST /match requests.", &self.metrics.matches_found),
+            ("match_microseconds_total", "Time spent matching names.", &self.metrics.match_microseconds),
+        ].iter().map(|(name, help, value)| {
+            format!("# HELP pseudo_jaro_winkler_{0} {1}\n# TYPE pseudo_jaro_winkler_{0} counter\npseudo_jaro_winkler_{0} {2}\n", name, help, value.load(Ordering::Relaxed))
+        }).collect()
+    }
+}
@@ -0,0 +1,184 @@
+// This file is part of the IPUMS's pseudo_jaro_winkler.
+// For copyright and licensing information, see the NOTICE and LICENSE files
+// in this project's top-level directory, and also on-line at:
+//   https://github.com/ipums/pseudo_jaro_winkler
+
+//! Splitting names_b into shards which are each indexed and matched on their own, for candidate
+//! lists too large to index all at once.
+//!
+//! Each shard writes the usual output files, using the indices of its matches within all of
+//! names_b, into a directory of its own. The shards can be matched one after another with
+//! `pseudo_jaro_winkler_sharded_streaming`, which merges them into the final output itself, or on
+//! different machines, with their directories then merged with `merge_shards`.
+
+use std::fs::{create_dir_all, read_dir, remove_dir_all, File};
+use std::io::{BufRead, BufReader};
+use std::io::prelude::*;
+use std::ops::Range;
+use std::path::PathBuf;
+use std::sync::Arc;
+use rayon::prelude::*;
+use crate::{keep_top_k, pseudo_jaro_winkler_with_index, sort_matches, CandidateIndex, Checkpoint, MatchOptions, MatchOrder, MatchOutcome, ProgressReporter};
+use crate::checkpoint::{write_file, Manifest};
+
+/// Passes the progress of each shard on to the reporter of the whole run, which is started and
+/// finished once rather than for every shard.
+struct ShardProgress(Arc<dyn ProgressReporter>);
+
+impl ProgressReporter for ShardProgress {
+    fn start(&self, _total: Option<u64>) {}
+
+    fn advance(&self, count: u64) {
+        self.0.advance(count);
+    }
+
+    fn finish(&self) {}
+}
+
+/// Finds the indices of names_b that belong to a shard, splitting them into `shard_count` shards
+/// of nearly equal size.
+///
+/// # Arguments
+///
+/// * `names_b_len`: The number of names in names_b.
+/// * `shard`: The index of the shard, starting at 0.
+/// * `shard_count`: The total number of shards.
+pub fn shard_range(names_b_len: usize, shard: usize, shard_count: usize) -> Range<usize> {
+    assert!(shard < shard_count, "shard {} is out of range for {} shards", shard, shard_count);
+    (shard * names_b_len / shard_count)..((shard + 1) * names_b_len / shard_count)
+}
+
+/// Compares two vectors of strings using the pseudo jaro winkler algorithm, indexing and matching
+/// names_b one shard at a time so that only a single shard's index is in memory at once. This is
+/// `pseudo_jaro_winkler_sharded_streaming` with every name of names_a matched in a single chunk.
+///
+/// # Arguments
+///
+/// * `names_a`: List of names in the first dataset.
+/// * `names_b`: List of names in the second dataset.
+/// * `output_dir`: The location of the output directory to write matches to.
+/// * `options`: How to score and write the matches. See `MatchOptions`.
+/// * `shard_count`: The number of shards to split names_b into.
+pub fn pseudo_jaro_winkler_sharded(names_a: &[String], names_b: &[String], output_dir: PathBuf, options: &MatchOptions, shard_count: usize) -> MatchOutcome {
+    let names_a_iter = || names_a.iter().cloned();
+    let names_b_shard = |range: Range<usize>| names_b[range].to_vec();
+    pseudo_jaro_winkler_sharded_streaming(names_a_iter, names_b_shard, names_b.len(), output_dir, options, shard_count, names_a.len().max(1))
+}
+
+/// Compares a stream of names to names_b one shard of names_b at a time, so that only a single
+/// shard's names and index are in memory at once, along with a chunk of names_a as in
+/// `pseudo_jaro_winkler_streaming`. Each shard is written to a `shard_X` directory within the
+/// output directory, which is removed once the shards are merged into the output directory.
+///
+/// The progress reporter is started once for the whole run, with every name of names_a counted
+/// once for each shard it is still to be matched against.
+///
+/// If the run is stopped, only the names matched against every shard are merged and returned as
+/// completed. Those are the names the last shard finished, or none if the run stopped before the
+/// last shard, since the shards after the stopped one haven't matched any names. When
+/// checkpointing, each shard directory has a manifest of its own and the shard directories are
+/// kept, so that resuming the run into the same output directory skips the names each shard
+/// already wrote. See `Checkpoint`.
+///
+/// # Arguments
+///
+/// * `names_a`: Streams the names in the first dataset from the start, once for each shard.
+/// * `names_b`: Reads the names of names_b within a range of indices, once for each shard.
+/// * `names_b_len`: The number of names in names_b.
+/// * `output_dir`: The location of the output directory to write matches to.
+/// * `options`: How to score and write the matches. See `MatchOptions`.
+/// * `shard_count`: The number of shards to split names_b into.
+/// * `chunk_size`: The number of names from `names_a` to match at a time.
+pub fn pseudo_jaro_winkler_sharded_streaming<A, I, B>(names_a: A, names_b: B, names_b_len: usize, output_dir: PathBuf, options: &MatchOptions, shard_count: usize, chunk_size: usize) -> MatchOutcome
+where A: Fn() -> I, I: IntoIterator<Item = String>, B: Fn(Range<usize>) -> Vec<String> {
+    // The progress covers names_a once for each shard, less the names already written by each shard
+    let total = match names_a().into_iter().size_hint() {
+        (lower, Some(upper)) if lower == upper => Some((0..shard_count).map(|shard| {
+            let shard_dir = output_dir.join(format!("shard_{}", shard));
+            let resumed_count = Manifest::resumed_count(&shard_dir, options).unwrap_or_else(|e| panic!("Error checkpointing into {}: {}", shard_dir.display(), e));
+            upper.saturating_sub(resumed_count) as u64
+        }).sum()),
+        _ => None,
+    };
+    options.progress.start(total);
+    let shard_options = MatchOptions { progress: Arc::new(ShardProgress(options.progress.clone())), ..options.clone() };
+    let mut outcome = MatchOutcome::default();
+    let mut shard_dirs = Vec::new();
+    for shard in 0..shard_count {
+        let range = shard_range(names_b_len, shard, shard_count);
+        let shard_dir = output_dir.join(format!("shard_{}", shard));
+        let candidate_index = CandidateIndex::new_shard(&names_b(range.clone()), range.start);
+        outcome = pseudo_jaro_winkler_with_index(names_a(), &candidate_index, shard_dir.clone(), &shard_options, chunk_size);
+        shard_dirs.push(shard_dir);
+        if outcome.is_stopped {
+            break;
+        }
+    }
+    if shard_dirs.len() < shard_count {
+        outcome.completed.clear();
+    }
+    let completed = outcome.completed.iter().flat_map(Range::clone).collect::<Vec<_>>();
+    merge_shard_names(&shard_dirs, &completed, output_dir, options.top_k, options.order);
+    // A stopped run keeps its shard directories so that it can be resumed
+    if !outcome.is_stopped || options.checkpoint == Checkpoint::Off {
+        shard_dirs.iter().for_each(|shard_dir| remove_dir_all(shard_dir).unwrap());
+    }
+    options.progress.finish();
+    outcome
+}
+
+/// Merges the output directories of the shards of names_b into a single output directory, as if
+/// names_b had been matched all at once. The matches of each name are written in shard order, or
+/// from best to worst when keeping the `top_k` best matches, unless another order is given. The
+/// shards must have been matched with the same threshold, which isn't applied again since the
+/// written scores are rounded.
+///
+/// # Arguments
+///
+/// * `shard_dirs`: The output directories of every shard.
+/// * `output_dir`: The location of the output directory to write the merged matches to.
+/// * `top_k`: Only write the best `top_k` matches for each name. See `keep_top_k`.
+/// * `order`: The order in which to write the matches of each name. See `sort_matches`.
+pub fn merge_shards(shard_dirs: &[PathBuf], output_dir: PathBuf, top_k: Option<usize>, order: MatchOrder) {
+    assert!(!shard_dirs.is_empty(), "there must be at least one shard to merge");
+    let a_ids = read_dir(&shard_dirs[0]).unwrap().filter_map(|entry| {
+        let file_name = entry.unwrap().file_name();
+        file_name.to_str()?.strip_suffix(".txt")?.parse::<usize>().ok()
+    }).collect::<Vec<_>>();
+    merge_shard_names(shard_dirs, &a_ids, output_dir, top_k, order);
+}
+
+/// Merges the output files of only some of the names in names_a, the same as `merge_shards`. This
+/// merges the shards of a stopped run, whose later shards don't have every name's output file.
+///
+/// # Arguments
+///
+/// * `shard_dirs`: The output directories of every shard.
+/// * `a_ids`: The indices within names_a of the names to merge, which every shard must have
+///   written.
+/// * `output_dir`: The location of the output directory to write the merged matches to.
+/// * `top_k`: Only write the best `top_k` matches for each name. See `keep_top_k`.
+/// * `order`: The order in which to write the matches of each name. See `sort_matches`.
+pub fn merge_shard_names(shard_dirs: &[PathBuf], a_ids: &[usize], mut output_dir: PathBuf, top_k: Option<usize>, order: MatchOrder) {
+    create_dir_all(&mut output_dir).unwrap();
+    a_ids.par_iter().for_each(|a_id| {
+        let file_name = format!("{}.txt", a_id);
+        let mut matches = shard_dirs.iter().flat_map(|shard_dir| {
+            let shard_path = shard_dir.join(&file_name);
+            let shard_file = File::open(&shard_path).unwrap_or_else(|_| panic!("Error opening shard output: {}", shard_path.display()));
+            BufReader::new(shard_file).lines().map(|line| {
+                let line = line.unwrap();
+                let (b_id, jw) = line.split_once(',').unwrap_or_else(|| panic!("Error parsing shard output: {}", line));
+                (b_id.parse::<usize>().unwrap(), jw.parse::<f32>().unwrap())
+            }).collect::<Vec<_>>()
+        }).collect::<Vec<_>>();
+        if let Some(top_k) = top_k {
+            keep_top_k(&mut matches, top_k);
+        }
+        sort_matches(&mut matches, order);
+        let output_path = output_dir.join(&file_name);
+        write_file(&output_path, false, |file| {
+            matches.iter().try_for_each(|(b_id, jw)| writeln!(file, "{},{:.2}", b_id, jw))
+        }).unwrap_or_else(|e| panic!("Error writing {}: {}", output_path.display(), e));
+    });
+}
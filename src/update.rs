@@ -0,0 +1,232 @@
+// This file is part of the IPUMS's pseudo_jaro_winkler.
+// For copyright and licensing information, see the NOTICE and LICENSE files
+// in this project's top-level directory, and also on-line at:
+//   https://github.com/ipums/pseudo_jaro_winkler
+
+//! Inserting and removing candidate names after an index is built.
+//!
+//! The arrays of a `CandidateIndex` are never modified once built. Inserted names are kept to the
+//! side and scored one at a time along with the arrays, and removed names are filtered out of the
+//! matches. Compacting the index rebuilds the arrays with the updates folded in, which can be done
+//! on a background thread while the index keeps being queried and updated.
+
+use alloc::collections::{BTreeMap, BTreeSet};
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+#[cfg(feature = "std")]
+use std::thread::{self, JoinHandle};
+use itertools::Itertools;
+use crate::{is_valid_name, maskify, padded_letters, score_padded_letters, CandidateIndex};
+
+/// The fewest updates that make an index worth compacting. See `CandidateIndex::needs_compaction`.
+const MIN_COMPACTION_UPDATES: usize = 1024;
+
+/// A name inserted into the index since it was last compacted.
+#[derive(Clone)]
+struct InsertedName {
+    /// The letter indices of the name. See `padded_letters`.
+    letters: [u8; 16],
+    /// The indices of the candidates with this name.
+    b_ids: Vec<usize>,
+}
+
+/// An insertion or removal, recorded so that it can be replayed once a background compaction
+/// finishes.
+#[derive(Clone)]
+#[cfg_attr(not(feature = "std"), allow(dead_code))]
+enum Update {
+    Insert(usize, String),
+    Remove(usize),
+}
+
+/// The names inserted into and removed from an index since it was built or last compacted.
+#[derive(Clone, Default)]
+pub(crate) struct Updates {
+    /// The inserted names along with the indices of the candidates with each one, sorted by name.
+    inserted_by_name: BTreeMap<String, InsertedName>,
+    /// The name of each inserted candidate, by its index.
+    inserted_names: BTreeMap<usize, String>,
+    /// The indices of the candidates in the index's arrays which have been removed.
+    removed: BTreeSet<usize>,
+    /// The indices of every candidate in the index's arrays, sorted. They are gathered the first
+    /// time a candidate is inserted or removed, so that looking one up doesn't scan the arrays.
+    built_b_ids: Option<Vec<u64>>,
+    /// The updates made since a background compaction started, if one is running.
+    since_compaction: Option<Vec<Update>>,
+}
+
+impl Updates {
+    #[cfg(feature = "io")]
+    pub(crate) fn is_empty(&self) -> bool {
+        self.inserted_names.is_empty() && self.removed.is_empty()
+    }
+
+    /// Whether a candidate in the index's arrays has been removed.
+    #[inline]
+    pub(crate) fn is_removed(&self, b_id: usize) -> bool {
+        !self.removed.is_empty() && self.removed.contains(&b_id)
+    }
+
+    /// The indices of the inserted candidates with a name.
+    pub(crate) fn inserted_b_ids(&self, name: &str) -> &[usize] {
+        self.inserted_by_name.get(name).map_or(&[], |inserted| inserted.b_ids.as_slice())
+    }
+
+    /// The index and name of every inserted candidate.
+    pub(crate) fn inserted_names(&self) -> impl Iterator<Item = (usize, &str)> {
+        self.inserted_names.iter().map(|(&b_id, name)| (b_id, name.as_str()))
+    }
+
+    /// Scores a query against the inserted names, returning the index, name and score of every
+    /// candidate at or above `min_jaro_winkler`. Empty names aren't scored, see `EmptyNames`.
+    pub(crate) fn score(&self, query_name: &str, min_jaro_winkler: f32) -> Vec<(usize, &str, f32)> {
+        if self.inserted_by_name.is_empty() {
+            return Vec::new();
+        }
+        let query_masks_lookup = maskify(query_name);
+        let query_partial = ((1.0 / query_name.len() as f32) * 1024.0) as u16;
+        self.inserted_by_name.iter().filter(|(name, _)| !name.is_empty()).flat_map(|(name, inserted)| {
+            let jw = score_padded_letters(&inserted.letters, name.len() as u8, &query_masks_lookup, query_partial);
+            let b_ids = if jw >= min_jaro_winkler { inserted.b_ids.as_slice() } else { &[] };
+            b_ids.iter().map(move |&b_id| (b_id, name.as_str(), jw))
+        }).collect()
+    }
+
+    fn record(&mut self, update: Update) {
+        if let Some(since_compaction) = &mut self.since_compaction {
+            since_compaction.push(update);
+        }
+    }
+}
+
+/// A compaction of a `CandidateIndex` running on a background thread. See
+/// `CandidateIndex::start_compaction`.
+#[cfg(feature = "std")]
+pub struct Compaction {
+    handle: JoinHandle<CandidateIndex>,
+}
+
+#[cfg(feature = "std")]
+impl Compaction {
+    /// Whether the compaction is done, so that `CandidateIndex::finish_compaction` won't block.
+    pub fn is_finished(&self) -> bool {
+        self.handle.is_finished()
+    }
+}
+
+impl CandidateIndex {
+    /// Inserts a candidate name into the index, so that it is matched from then on.
+    ///
+    /// # Arguments
+    ///
+    /// * `b_id`: The index of the candidate written with its matches. It must not already be in
+    ///   the index, unless it has been removed.
+    /// * `name`: The candidate's name. Invalid names other than empty ones are left out, as in
+    ///   `CandidateIndex::new`.
+    pub fn insert(&mut self, b_id: usize, name: &str) {
+        let is_live = self.is_built(b_id) && !self.updates.is_removed(b_id);
+        assert!(!is_live && !self.updates.inserted_names.contains_key(&b_id), "candidate {} is already in the index", b_id);
+        if !is_valid_name(name) && !name.is_empty() {
+            return;
+        }
+        self.updates.record(Update::Insert(b_id, name.to_string()));
+        self.updates.inserted_by_name.entry(name.to_string()).or_insert_with(|| {
+            InsertedName { letters: padded_letters(name), b_ids: Vec::new() }
+        }).b_ids.push(b_id);
+        self.updates.inserted_names.insert(b_id, name.to_string());
+    }
+
+    /// Removes a candidate from the index, so that it is no longer matched. Returns whether the
+    /// candidate was in the index.
+    ///
+    /// # Arguments
+    ///
+    /// * `b_id`: The index of the candidate written with its matches.
+    pub fn remove(&mut self, b_id: usize) -> bool {
+        let was_removed = if let Some(name) = self.updates.inserted_names.remove(&b_id) {
+            let inserted = self.updates.inserted_by_name.get_mut(&name).unwrap();
+            inserted.b_ids.retain(|&inserted_b_id| inserted_b_id != b_id);
+            if inserted.b_ids.is_empty() {
+                self.updates.inserted_by_name.remove(&name);
+            }
+            true
+        } else {
+            self.is_built(b_id) && self.updates.removed.insert(b_id)
+        };
+        if was_removed {
+            self.updates.record(Update::Remove(b_id));
+        }
+        was_removed
+    }
+
+    /// Whether a candidate is in the index's arrays, including the empty names, even if it has been
+    /// removed since.
+    fn is_built(&mut self, b_id: usize) -> bool {
+        let b_ids = &self.b_ids;
+        let built_b_ids = self.updates.built_b_ids.get_or_insert_with(|| b_ids.iter().copied().sorted_unstable().collect());
+        built_b_ids.binary_search(&(b_id as u64)).is_ok() || self.empty_b_ids.binary_search(&(b_id as u64)).is_ok()
+    }
+
+    /// The number of candidates in the index, including the empty names and the inserted ones but
+    /// not the removed ones.
+    #[cfg(feature = "io")]
+    pub(crate) fn candidate_count(&self) -> usize {
+        self.b_ids.len() + self.empty_b_ids.len() + self.updates.inserted_names.len() - self.updates.removed.len()
+    }
+
+    /// Whether enough names have been inserted or removed that the index should be compacted.
+    /// Inserted names are scored one at a time, so matching slows down as they pile up.
+    pub fn needs_compaction(&self) -> bool {
+        let update_count = self.updates.inserted_names.len() + self.updates.removed.len();
+        update_count >= MIN_COMPACTION_UPDATES.max(self.name_lens.len() / 16)
+    }
+
+    /// Rebuilds the index with the inserted and removed names folded in.
+    pub fn compact(&mut self) {
+        assert!(self.updates.since_compaction.is_none(), "a background compaction is already running");
+        *self = self.compacted();
+    }
+
+    /// Starts rebuilding the index with the inserted and removed names folded in, on a background
+    /// thread. The index can still be queried and updated in the meantime, and the updates are
+    /// applied to the rebuilt index by `CandidateIndex::finish_compaction`.
+    #[cfg(feature = "std")]
+    pub fn start_compaction(&mut self) -> Compaction {
+        assert!(self.updates.since_compaction.is_none(), "a background compaction is already running");
+        let snapshot = self.clone();
+        self.updates.since_compaction = Some(Vec::new());
+        Compaction { handle: thread::spawn(move || snapshot.compacted()) }
+    }
+
+    /// Replaces the index with the one rebuilt by a background compaction, waiting for it if it
+    /// isn't done yet, and applies any updates made since it started.
+    #[cfg(feature = "std")]
+    pub fn finish_compaction(&mut self, compaction: Compaction) {
+        let since_compaction = self.updates.since_compaction.take().expect("no background compaction is running");
+        *self = compaction.handle.join().expect("the background compaction panicked");
+        since_compaction.into_iter().for_each(|update| match update {
+            Update::Insert(b_id, name) => self.insert(b_id, &name),
+            Update::Remove(b_id) => { self.remove(b_id); },
+        });
+    }
+
+    /// Builds a new index of the current candidates, including the inserted names and excluding
+    /// the removed ones.
+    pub(crate) fn compacted(&self) -> CandidateIndex {
+        let mut lookup_by_name = BTreeMap::new();
+        (0..self.name_lens.len()).for_each(|name_index| {
+            let b_ids = self.b_ids(name_index).iter().map(|&b_id| b_id as usize).filter(|&b_id| !self.updates.is_removed(b_id)).collect::<Vec<_>>();
+            if !b_ids.is_empty() {
+                lookup_by_name.insert(self.name(name_index), b_ids);
+            }
+        });
+        let empty_b_ids = self.empty_b_ids.iter().map(|&b_id| b_id as usize).filter(|&b_id| !self.updates.is_removed(b_id)).collect::<Vec<_>>();
+        if !empty_b_ids.is_empty() {
+            lookup_by_name.insert("", empty_b_ids);
+        }
+        self.updates.inserted_by_name.iter().for_each(|(name, inserted)| {
+            lookup_by_name.entry(name.as_str()).or_insert_with(Vec::new).extend_from_slice(&inserted.b_ids);
+        });
+        CandidateIndex::from_lookup_by_name(lookup_by_name)
+    }
+}